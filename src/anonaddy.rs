@@ -1,58 +1,210 @@
-use async_trait::async_trait;
-use log::info;
-use serde::Deserialize;
-
-use crate::email_alias::{Alias, AliasError, AliasService};
+use std::ffi::OsString;
+use std::fmt::Debug;
 
-#[derive(Deserialize, Debug)]
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ConfigError, Problem};
+use crate::email_alias::{
+    AccountSummary, Alias, AliasCounters, AliasDomain, AliasQuery, AliasService, DeletedAliases,
+    DomainKind,
+};
+use crate::error::{decode, ApiError};
+use crate::redact;
+use crate::secret::Secret;
+
+/// Missing fields take their default, so fields this does not need being removed or renamed do not stop the account
+/// being read.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+#[allow(dead_code)]
 pub struct Account {
     id: String,
     username: String,
-    from_name: String,
-    email_subject: String,
+    from_name: Option<String>,
+    email_subject: Option<String>,
     banner_location: String,
-    bandwidth: i16,
-    username_count: i16,
-    username_limit: i16,
+    bandwidth: u64,
+    username_count: u32,
+    username_limit: u32,
     default_recipient_id: String,
     default_alias_domain: String,
     default_alias_format: String,
-    subscription: String,
-    subscription_ends_at: Option<i16>,
-    bandwidth_limit: i16,
-    recipient_count: i16,
-    recipient_limit: i16,
-    active_domain_count: i16,
-    active_domain_limit: i16,
-    active_shared_domain_alias_count: i16,
-    active_shared_domain_alias_limit: i16,
-    total_emails_forwarded: String,
-    total_emails_blocked: String,
-    total_emails_replied: String,
-    total_emails_sent: String,
+    /// Not set on self-hosted instances.
+    subscription: Option<String>,
+    subscription_ends_at: Option<String>,
+    bandwidth_limit: u64,
+    recipient_count: u32,
+    recipient_limit: u32,
+    active_domain_count: u32,
+    active_domain_limit: u32,
+    active_shared_domain_alias_count: u32,
+    active_shared_domain_alias_limit: u32,
+    total_emails_forwarded: u64,
+    total_emails_blocked: u64,
+    total_emails_replied: u64,
+    total_emails_sent: u64,
     created_at: String,
     updated_at: String,
 }
 
-#[derive(Deserialize, Debug)]
+impl From<Account> for AccountSummary {
+    /// Only aliases on shared domains are limited, and a limit of 0 is taken as no limit.
+    fn from(account: Account) -> Self {
+        let limit = |limit: u32| (limit > 0).then_some(limit);
+        AccountSummary {
+            provider: PROVIDER.to_string(),
+            version: None,
+            username: account.username,
+            subscription: account.subscription,
+            alias_count: account.active_shared_domain_alias_count,
+            alias_limit: limit(account.active_shared_domain_alias_limit),
+            username_count: account.username_count,
+            username_limit: limit(account.username_limit),
+        }
+    }
+}
+
+/// Only the fields needed to identify and act on an alias are required, so that other fields being added, removed or
+/// renamed by the service do not stop aliases being read.
+#[derive(Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct AnonAddyAlias {
     pub id: String,
+    #[serde(default)]
     pub user_id: String,
+    #[serde(default)]
     pub aliasable_id: Option<String>,
+    #[serde(default)]
     pub aliasable_type: Option<String>,
     pub local_part: String,
+    #[serde(default)]
     pub extension: Option<String>,
     pub domain: String,
     pub email: String,
     pub active: bool,
+    #[serde(default)]
     pub description: Option<String>,
-    pub emails_forwarded: i16,
-    pub emails_blocked: i16,
-    pub emails_replied: i16,
-    pub emails_sent: i16,
-    pub recipients: Vec<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    #[serde(default)]
+    pub emails_forwarded: u64,
+    #[serde(default)]
+    pub emails_blocked: u64,
+    #[serde(default)]
+    pub emails_replied: u64,
+    #[serde(default)]
+    pub emails_sent: u64,
+    #[serde(default)]
+    pub recipients: Vec<AnonAddyRecipient>,
+    #[serde(default, with = "api_time")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "api_time")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "api_time")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// The name replies and sends from the alias appear to come from. Not returned by AnonAddy before the rebrand to
+    /// addy.io, nor are the `last_*` times.
+    #[serde(default)]
+    pub from_name: Option<String>,
+    #[serde(default, with = "api_time")]
+    pub last_forwarded: Option<DateTime<Utc>>,
+    #[serde(default, with = "api_time")]
+    pub last_blocked: Option<DateTime<Utc>>,
+    #[serde(default, with = "api_time")]
+    pub last_replied: Option<DateTime<Utc>>,
+    #[serde(default, with = "api_time")]
+    pub last_sent: Option<DateTime<Utc>>,
+}
+
+impl AnonAddyAlias {
+    /// The format the alias was generated with, as accepted when creating an alias, if its local part has the exact
+    /// shape addy.io generates: the alias ID for `uuid`, two words and a number such as `marvelous.notebook25` for
+    /// `random_words`, or 8 lowercase letters and digits including a digit for `random_characters`.
+    ///
+    /// Anything else returns `None`, so the account's default format is used for the replacement. This covers custom
+    /// aliases, whose local part cannot be reused, and the odd random alias with no digit, which cannot be told apart
+    /// from a custom word such as `shopping`.
+    fn format(&self) -> Option<&'static str> {
+        let local_part = self.local_part.as_str();
+        let is_random_words = local_part.split_once('.').is_some_and(|(first, second)| {
+            let word = second.trim_end_matches(|character: char| character.is_ascii_digit());
+            is_lowercase_word(first) && is_lowercase_word(word) && word.len() < second.len()
+        });
+        let is_random_characters = local_part.len() == 8
+            && local_part
+                .chars()
+                .all(|character| character.is_ascii_lowercase() || character.is_ascii_digit())
+            && local_part
+                .chars()
+                .any(|character| character.is_ascii_digit());
+        if local_part == self.id {
+            Some("uuid")
+        } else if is_random_words {
+            Some("random_words")
+        } else if is_random_characters {
+            Some("random_characters")
+        } else {
+            None
+        }
+    }
+}
+
+fn is_lowercase_word(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|character| character.is_ascii_lowercase())
+}
+
+/// Where an alias forwards to, when not the account's default recipient.
+#[derive(Serialize, Deserialize)]
+pub struct AnonAddyRecipient {
+    pub id: String,
+    pub email: String,
+}
+
+impl Debug for AnonAddyRecipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnonAddyRecipient")
+            .field("id", &self.id)
+            .field("email", &redact::Email(&self.email))
+            .finish()
+    }
+}
+
+/// Masks the address in privacy mode, as aliases are written to the debug log.
+impl Debug for AnonAddyAlias {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let local_part = if redact::privacy_mode() {
+            "***"
+        } else {
+            &self.local_part
+        };
+        f.debug_struct("AnonAddyAlias")
+            .field("id", &self.id)
+            .field("user_id", &self.user_id)
+            .field("aliasable_id", &self.aliasable_id)
+            .field("aliasable_type", &self.aliasable_type)
+            .field("local_part", &local_part)
+            .field("extension", &self.extension)
+            .field("domain", &self.domain)
+            .field("email", &redact::Email(&self.email))
+            .field("active", &self.active)
+            .field("description", &self.description)
+            .field("emails_forwarded", &self.emails_forwarded)
+            .field("emails_blocked", &self.emails_blocked)
+            .field("emails_replied", &self.emails_replied)
+            .field("emails_sent", &self.emails_sent)
+            .field("recipients", &self.recipients)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .field("deleted_at", &self.deleted_at)
+            .field("from_name", &self.from_name)
+            .field("last_forwarded", &self.last_forwarded)
+            .field("last_blocked", &self.last_blocked)
+            .field("last_replied", &self.last_replied)
+            .field("last_sent", &self.last_sent)
+            .finish()
+    }
 }
 
 impl Alias for AnonAddyAlias {
@@ -60,6 +212,10 @@ impl Alias for AnonAddyAlias {
         self.active
     }
 
+    fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
     fn get_id(&self) -> &str {
         self.id.as_ref()
     }
@@ -71,6 +227,100 @@ impl Alias for AnonAddyAlias {
     fn get_description(&self) -> Option<&str> {
         self.description.as_deref()
     }
+
+    fn get_provider(&self) -> &str {
+        PROVIDER
+    }
+
+    fn get_recipients(&self) -> Vec<&str> {
+        self.recipients
+            .iter()
+            .map(|recipient| recipient.email.as_str())
+            .collect()
+    }
+
+    fn get_created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    fn get_updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    fn get_counters(&self) -> AliasCounters {
+        AliasCounters {
+            forwarded: self.emails_forwarded,
+            blocked: self.emails_blocked,
+            replied: self.emails_replied,
+            sent: self.emails_sent,
+        }
+    }
+
+    fn get_domain(&self) -> &str {
+        &self.domain
+    }
+}
+
+/// Reads and writes times in the form the API gives them, e.g. `2019-10-01 09:00:00`, which are always in UTC.
+///
+/// RFC 3339 times are read too, and a time in any other form is read as missing with a warning rather than failing
+/// the whole alias, as none of these times are needed to act on it.
+mod api_time {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use log::warn;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&time.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(
+            Option::<String>::deserialize(deserializer)?.and_then(|time| {
+                NaiveDateTime::parse_from_str(&time, FORMAT)
+                    .map(|time| time.and_utc())
+                    .or_else(|_| DateTime::parse_from_rfc3339(&time).map(|time| time.to_utc()))
+                    .inspect_err(|error| warn!("Ignoring invalid time {:?}: {}", time, error))
+                    .ok()
+            }),
+        )
+    }
+}
+
+/// One of the account's usernames, which can receive mail at `<anything>@<username>.<instance domain>`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnonAddyUsername {
+    pub id: String,
+    pub username: String,
+    pub active: bool,
+    pub catch_all: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnonAddyDomain {
+    pub id: String,
+    pub domain: String,
+    pub active: bool,
+    pub catch_all: bool,
+}
+
+/// The version of the instance, which older self-hosted instances do not report.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppVersion {
+    pub version: String,
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -78,64 +328,276 @@ pub struct AnonAddyResponse<T> {
     pub data: Vec<T>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct AnonAddyItemResponse<T> {
+    pub data: T,
+}
+
+/// The name of the service, as reported for accounts and aliases.
+const PROVIDER: &str = "addy.io";
+
+/// The host used unless another is configured.
+const DEFAULT_HOST: &str = "https://app.addy.io";
+
+/// Environment variables from before the rebrand to addy.io, with the variables that replace them.
+const LEGACY_VARIABLES: [(&str, &str); 3] = [
+    ("ADDY_TOKEN", "ANONADDY_TOKEN"),
+    ("ADDY_TOKEN_FILE", "ANONADDY_TOKEN_FILE"),
+    ("ADDY_HOST", "ANONADDY_HOST"),
+];
+
+/// Reads an environment variable, falling back to its name from before the rebrand to addy.io, e.g. `ANONADDY_TOKEN`
+/// for `ADDY_TOKEN`.
+pub fn var_os(name: &str) -> Option<OsString> {
+    std::env::var_os(name).or_else(|| {
+        LEGACY_VARIABLES
+            .iter()
+            .find(|(current, _)| *current == name)
+            .and_then(|(_, legacy)| std::env::var_os(legacy))
+    })
+}
+
+/// Decodes each item of a listing on its own, skipping any that cannot be decoded with a warning rather than failing
+/// the whole listing.
+fn decode_each<T: DeserializeOwned>(items: Vec<serde_json::Value>, kind: &str) -> Vec<T> {
+    items
+        .into_iter()
+        .filter_map(|item| {
+            let id = item
+                .get("id")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("without an ID")
+                .to_string();
+            serde_json::from_value(item)
+                .inspect_err(|error| {
+                    warn!(
+                        "Skipping {} {} as it could not be read: {}",
+                        kind, id, error
+                    )
+                })
+                .ok()
+        })
+        .collect()
+}
+
 pub struct AnonAddy<'a> {
     client: &'a reqwest::Client,
-    token: String,
+    token: Secret,
     host: String,
 }
 
 impl<'a> AnonAddy<'a> {
-    /// Creates a new instance to query against an AnonAddy instance.
+    /// Creates a new instance to query against addy.io, or a self-hosted AnonAddy instance.
     ///
-    /// For this to work, a `ANONADDY_TOKEN` environment variable must be set. If it is not set, this will panic.
-    /// By default, this will use `app.anonaddy.com`, but this can be overriden by setting the `ANONADDY_HOST` environment variable to the desired instance URL.
+    /// For this to work, a token must be given. If it is not, a [`ConfigError`] is returned. See
+    /// [`Config::read_credentials`](crate::config::Config::read_credentials) for where it is read from.
+    /// By default, this will use `app.addy.io`, but this can be overriden by giving the desired instance URL as the host.
     ///
     /// # Examples
     /// Only providing the token:
     /// ```
     /// let client = reqwest::Client::new();
-    /// std::env::set_var("ANONADDY_TOKEN", "test-token");
-    /// let anonaddy = ANONADDY_TOKEN::new(&client);
+    /// let anonaddy = AnonAddy::new(&client, Some(Secret::new("test-token")), None)?;
     /// ```
     /// Providing the token and the host:
     /// ```
     /// let client = reqwest::Client::new();
-    /// std::env::set_var("ANONADDY_TOKEN", "test-token");
-    /// std::env::set_var("ANONADDY_HOST", "https://my-anonaddy-instance.com");
-    /// let anonaddy = ANONADDY_TOKEN::new(&client);
+    /// let host = "https://my-addy-instance.com".to_string();
+    /// let anonaddy = AnonAddy::new(&client, Some(Secret::new("test-token")), Some(host))?;
     /// ```
-    pub fn new(client: &'a reqwest::Client) -> Self {
-        let token = std::env::var("ANONADDY_TOKEN").expect("Please provide ANONADDY_TOKEN");
-        let host = std::env::var("ANONADDY_HOST")
-            .unwrap_or_else(|_| "https://app.anonaddy.com".to_string());
-        AnonAddy {
+    pub fn new(
+        client: &'a reqwest::Client,
+        token: Option<Secret>,
+        host: Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut problems = vec![];
+        if token.is_none() {
+            problems.push(Problem::missing("providers.addy.token", "ADDY_TOKEN"));
+        }
+        let host = host.unwrap_or_else(|| DEFAULT_HOST.to_string());
+        if let Err(error) = reqwest::Url::parse(&host) {
+            problems.push(Problem::invalid(
+                "providers.addy.host",
+                &host,
+                &format!("is not a valid URL: {}", error),
+            ));
+        }
+        if !problems.is_empty() {
+            return Err(ConfigError::new(problems));
+        }
+        Ok(AnonAddy {
             client,
-            token,
+            token: token.unwrap(),
             host,
-        }
+        })
     }
 }
 
-#[async_trait]
-impl<'a> AliasService for AnonAddy<'a> {
-    async fn get_aliases(&self) -> Result<Vec<Box<dyn Alias>>, Box<dyn std::error::Error>> {
-        info!("Getting aliases from AnonAddy.");
+impl AnonAddy<'_> {
+    /// Gets the details of the account the token belongs to.
+    pub async fn get_account(&self) -> Result<Account, ApiError> {
+        info!("Getting account details from addy.io.");
         let response = self
-            .client
-            .get(format!("{}/api/v1/aliases", &(self.host)))
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", &(self.token)))
+            .request(reqwest::Method::GET, "/api/v1/account-details")
+            .send()
+            .await?;
+        if response.status() != 200 {
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(decode::<AnonAddyItemResponse<Account>>(response)
+            .await?
+            .data)
+    }
+
+    /// Gets the version of the instance, or `None` for instances from before versions were reported.
+    pub async fn get_version(&self) -> Result<Option<AppVersion>, ApiError> {
+        let response = self
+            .request(reqwest::Method::GET, "/api/v1/app-version")
+            .send()
+            .await?;
+        match response.status().as_u16() {
+            200 => Ok(Some(decode(response).await?)),
+            404 => Ok(None),
+            _ => Err(ApiError::from_response(response).await),
+        }
+    }
+
+    /// The domain usernames are subdomains of, being the host without any `app.` prefix, e.g. `example.com` for a
+    /// self-hosted instance at `app.example.com`. addy.io still gives usernames subdomains of `anonaddy.com`.
+    fn username_domain(&self) -> String {
+        let host = reqwest::Url::parse(&self.host)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        match host.strip_prefix("app.").unwrap_or(&host) {
+            "addy.io" => "anonaddy.com".to_string(),
+            domain => domain.to_string(),
+        }
+    }
+
+    /// Lists the items at the path, skipping any that cannot be decoded.
+    async fn list<T: DeserializeOwned>(&self, path: &str, kind: &str) -> Result<Vec<T>, ApiError> {
+        let response = self.request(reqwest::Method::GET, path).send().await?;
+        if response.status() != 200 {
+            return Err(ApiError::from_response(response).await);
+        }
+        let items = decode::<AnonAddyResponse<serde_json::Value>>(response).await?;
+        Ok(decode_each(items.data, kind))
+    }
+
+    /// The filter to list the aliases with for each of the domains, without repeats. A domain that is not one of the
+    /// account's usernames or custom domains, such as a shared domain, has no filter.
+    async fn domain_filters(
+        &self,
+        domains: &[String],
+    ) -> Result<Vec<Option<(&'static str, String)>>, ApiError> {
+        if domains.is_empty() {
+            return Ok(vec![None]);
+        }
+        let known = self.get_domains().await?;
+        let mut filters = vec![];
+        for name in domains {
+            let filter = known
+                .iter()
+                .find(|domain| domain.domain.eq_ignore_ascii_case(name))
+                .map(|domain| match domain.kind {
+                    DomainKind::Username => ("username", domain.id.clone()),
+                    DomainKind::Custom => ("domain", domain.id.clone()),
+                });
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        Ok(filters)
+    }
+
+    /// Gets a single alias, including any deleted one.
+    async fn fetch_alias(&self, id: &str) -> Result<AnonAddyAlias, ApiError> {
+        let response = self
+            .request(reqwest::Method::GET, &format!("/api/v1/aliases/{}", id))
             .send()
             .await?;
         if response.status() != 200 {
-            return Err(Box::new(AliasError::new(
-                "Failed to get aliases.".to_string(),
-            )));
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(decode::<AnonAddyItemResponse<AnonAddyAlias>>(response)
+            .await?
+            .data)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", &(self.host), path))
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", self.token.expose()))
+    }
+}
+
+#[async_trait]
+impl<'a> AliasService for AnonAddy<'a> {
+    async fn get_account_summary(&self) -> Result<AccountSummary, ApiError> {
+        // The version is only informational, so the summary is still given without it.
+        let version = match self.get_version().await {
+            Ok(Some(version)) => {
+                debug!("The instance is running version {}.", version.version);
+                Some(version)
+            }
+            Ok(None) => {
+                debug!("The instance does not report its version.");
+                None
+            }
+            Err(error) => {
+                warn!("Could not get the version of the instance: {}", error);
+                None
+            }
+        };
+        Ok(AccountSummary {
+            version: version.map(|version| version.version),
+            ..self.get_account().await?.into()
+        })
+    }
+
+    /// Lists the aliases matching the query.
+    ///
+    /// addy.io has no filter on a domain's name, only on the ID of a username (`username`) or custom domain
+    /// (`domain`), one at a time. So the domains asked for are looked up and the aliases listed for each of them.
+    /// Shared domains have no ID, so the aliases on them are listed unfiltered, and every domain is matched again once
+    /// the aliases have been listed.
+    async fn get_aliases(&self, query: &AliasQuery) -> Result<Vec<Box<dyn Alias>>, ApiError> {
+        info!("Getting aliases from addy.io.");
+        let mut filters = vec![];
+        if let Some(active) = query.active {
+            filters.push(("filter[active]", active.to_string()));
+        }
+        match query.deleted {
+            DeletedAliases::Exclude => {}
+            DeletedAliases::Include => filters.push(("filter[deleted]", "with".to_string())),
+            DeletedAliases::Only => filters.push(("filter[deleted]", "only".to_string())),
+        }
+        if let Some(search) = &query.search {
+            filters.push(("filter[search]", search.clone()));
+        }
+        let mut aliases: Vec<AnonAddyAlias> = vec![];
+        for domain_filter in self.domain_filters(&query.domains).await? {
+            let response = self
+                .request(reqwest::Method::GET, "/api/v1/aliases")
+                .query(&filters)
+                .query(&domain_filter.as_slice())
+                .send()
+                .await?;
+            if response.status() != 200 {
+                return Err(ApiError::from_response(response).await);
+            }
+            let listed = decode::<AnonAddyResponse<serde_json::Value>>(response).await?;
+            for alias in decode_each::<AnonAddyAlias>(listed.data, "alias") {
+                if !aliases.iter().any(|listed| listed.id == alias.id) {
+                    aliases.push(alias);
+                }
+            }
         }
-        let aliases = response.json::<AnonAddyResponse<AnonAddyAlias>>().await?;
         let boxed: Vec<Box<dyn Alias>> = aliases
-            .data
             .into_iter()
+            .filter(|alias| query.matches_domain(&alias.email))
             .map(|alias| {
                 let boxed_alias: Box<dyn Alias> = Box::new(alias);
                 boxed_alias
@@ -145,85 +607,237 @@ impl<'a> AliasService for AnonAddy<'a> {
         Ok(boxed)
     }
 
-    async fn deactivate_alias(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    async fn get_domains(&self) -> Result<Vec<AliasDomain>, ApiError> {
+        info!("Getting usernames and domains from addy.io.");
+        let username_domain = self.username_domain();
+        let usernames = self
+            .list::<AnonAddyUsername>("/api/v1/usernames", "username")
+            .await?
+            .into_iter()
+            .map(|username| AliasDomain {
+                id: username.id,
+                domain: format!("{}.{}", username.username, username_domain),
+                kind: DomainKind::Username,
+                active: username.active,
+                catch_all: username.catch_all,
+            });
+        let domains = self
+            .list::<AnonAddyDomain>("/api/v1/domains", "domain")
+            .await?
+            .into_iter()
+            .map(|domain| AliasDomain {
+                id: domain.id,
+                domain: domain.domain,
+                kind: DomainKind::Custom,
+                active: domain.active,
+                catch_all: domain.catch_all,
+            });
+        Ok(usernames.chain(domains).collect())
+    }
+
+    async fn get_alias(&self, id: &str) -> Result<Box<dyn Alias>, ApiError> {
+        info!("Getting alias {} from addy.io.", id);
+        Ok(Box::new(self.fetch_alias(id).await?))
+    }
+
+    async fn deactivate_alias(&self, id: &str) -> Result<(), ApiError> {
         info!("Deactivating alias {}.", id);
         let response = self
-            .client
-            .delete(format!("{}/api/v1/active-aliases/{}", &(self.host), id))
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", &(self.token)))
+            .request(
+                reqwest::Method::DELETE,
+                &format!("/api/v1/active-aliases/{}", id),
+            )
+            .send()
+            .await?;
+        if response.status() != 204 {
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(())
+    }
+
+    async fn reactivate_alias(&self, id: &str) -> Result<(), ApiError> {
+        info!("Reactivating alias {}.", id);
+        let response = self
+            .request(reqwest::Method::POST, "/api/v1/active-aliases")
+            .json(&serde_json::json!({ "id": id }))
+            .send()
+            .await?;
+        if response.status() != 200 {
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(())
+    }
+
+    async fn delete_alias(&self, id: &str) -> Result<(), ApiError> {
+        info!("Deleting alias {}.", id);
+        let response = self
+            .request(reqwest::Method::DELETE, &format!("/api/v1/aliases/{}", id))
+            .send()
+            .await?;
+        if response.status() != 204 {
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(())
+    }
+
+    async fn set_description(&self, id: &str, description: &str) -> Result<(), ApiError> {
+        info!("Setting the description of alias {}.", id);
+        let response = self
+            .request(reqwest::Method::PATCH, &format!("/api/v1/aliases/{}", id))
+            .json(&serde_json::json!({ "description": description }))
+            .send()
+            .await?;
+        if response.status() != 200 {
+            return Err(ApiError::from_response(response).await);
+        }
+        Ok(())
+    }
+
+    /// Creates an alias on the same domain, with the same format, recipients and description. The alias is fetched
+    /// again first, as these are not part of [`Alias`].
+    async fn create_replacement(&self, alias: &dyn Alias) -> Result<Box<dyn Alias>, ApiError> {
+        info!("Creating a replacement for alias {}.", alias.get_id());
+        let old = self.fetch_alias(alias.get_id()).await?;
+        let mut body = serde_json::json!({
+            "domain": old.domain,
+            "description": old.description,
+        });
+        if let Some(format) = old.format() {
+            body["format"] = format.into();
+        }
+        if !old.recipients.is_empty() {
+            let recipient_ids: Vec<&str> = old
+                .recipients
+                .iter()
+                .map(|recipient| recipient.id.as_str())
+                .collect();
+            body["recipient_ids"] = recipient_ids.into();
+        }
+        let response = self
+            .request(reqwest::Method::POST, "/api/v1/aliases")
+            .json(&body)
+            .send()
+            .await?;
+        if response.status() != 201 {
+            return Err(ApiError::from_response(response).await);
+        }
+        let created = decode::<AnonAddyItemResponse<AnonAddyAlias>>(response).await?;
+        Ok(Box::new(created.data))
+    }
+
+    async fn create_blocked_alias(
+        &self,
+        domain: &AliasDomain,
+        local_part: &str,
+    ) -> Result<Box<dyn Alias>, ApiError> {
+        info!(
+            "Creating an inactive alias on {} to block an address.",
+            domain.domain
+        );
+        let response = self
+            .request(reqwest::Method::POST, "/api/v1/aliases")
+            .json(&serde_json::json!({
+                "domain": domain.domain,
+                "format": "custom",
+                "local_part": local_part,
+                "description": "Blocked after being found in a breach",
+            }))
             .send()
             .await?;
+        if response.status() != 201 {
+            return Err(ApiError::from_response(response).await);
+        }
+        let created = decode::<AnonAddyItemResponse<AnonAddyAlias>>(response).await?;
+        Ok(Box::new(created.data))
+    }
+
+    async fn disable_catch_all(&self, domain: &AliasDomain) -> Result<(), ApiError> {
+        info!("Disabling catch-all for {}.", domain.domain);
+        let path = match domain.kind {
+            DomainKind::Username => format!("/api/v1/catch-all-usernames/{}", domain.id),
+            DomainKind::Custom => format!("/api/v1/catch-all-domains/{}", domain.id),
+        };
+        let response = self.request(reqwest::Method::DELETE, &path).send().await?;
         if response.status() != 204 {
-            return Err(Box::new(AliasError::new(format!(
-                "Failed to deactivate alias {}.",
-                id
-            ))));
+            return Err(ApiError::from_response(response).await);
         }
         Ok(())
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::get_first, clippy::bool_assert_comparison)]
 mod tests {
     use httpmock::prelude::*;
     use serial_test::serial;
 
     use super::*;
+    use crate::email_alias::fakes::FakeAlias;
+    use crate::email_alias::AliasInfo;
 
     #[tokio::test]
-    #[serial]
-    #[should_panic(expected = "Please provide ANONADDY_TOKEN: NotPresent")]
-    async fn new_throw_error_if_token_variable_not_set() {
+    async fn new_return_error_if_token_not_given() {
         let client = reqwest::Client::new();
-        std::env::remove_var("ANONADDY_TOKEN");
-        std::env::remove_var("ANONADDY_HOST");
-        AnonAddy::new(&client);
+
+        let anonaddy = AnonAddy::new(&client, None, None);
+
+        assert_eq!(
+            anonaddy.err().unwrap().problems,
+            vec![Problem::missing("providers.addy.token", "ADDY_TOKEN")]
+        );
     }
 
     #[tokio::test]
-    #[serial]
-    async fn new_return_instance_if_token_variable_empty() {
+    async fn new_return_every_error_if_token_not_given_and_host_invalid() {
+        let client = reqwest::Client::new();
+
+        let anonaddy = AnonAddy::new(&client, None, Some("not a url".to_string()));
+
+        assert_eq!(
+            anonaddy.err().unwrap().problems,
+            vec![
+                Problem::missing("providers.addy.token", "ADDY_TOKEN"),
+                Problem::invalid(
+                    "providers.addy.host",
+                    "not a url",
+                    "is not a valid URL: relative URL without a base"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn new_return_instance_if_token_empty() {
         let client = reqwest::Client::new();
-        std::env::set_var("ANONADDY_TOKEN", "");
-        std::env::remove_var("ANONADDY_HOST");
 
-        let anonaddy = AnonAddy::new(&client);
+        let anonaddy = AnonAddy::new(&client, Some(Secret::new("")), None).unwrap();
 
         assert_eq!(anonaddy.client as *const _, &client as *const _);
-        assert_eq!(anonaddy.token, "");
-        assert_eq!(anonaddy.host, "https://app.anonaddy.com".to_string());
+        assert_eq!(anonaddy.token.expose(), "");
+        assert_eq!(anonaddy.host, "https://app.addy.io".to_string());
     }
 
     #[tokio::test]
-    #[serial]
-    async fn new_return_instance_if_token_variable_has_value() {
+    async fn new_return_instance_if_token_has_value() {
         let client = reqwest::Client::new();
-        std::env::set_var("ANONADDY_TOKEN", "test-token");
-        std::env::remove_var("ANONADDY_HOST");
 
-        let anonaddy = AnonAddy::new(&client);
+        let anonaddy = AnonAddy::new(&client, Some(Secret::new("test-token")), None).unwrap();
 
         assert_eq!(anonaddy.client as *const _, &client as *const _);
-        assert_eq!(anonaddy.token, "test-token");
-        assert_eq!(anonaddy.host, "https://app.anonaddy.com".to_string());
+        assert_eq!(anonaddy.token.expose(), "test-token");
+        assert_eq!(anonaddy.host, "https://app.addy.io".to_string());
     }
 
     #[tokio::test]
-    #[serial]
     async fn new_return_instance_with_custom_host_if_provided() {
         let client = reqwest::Client::new();
-        std::env::set_var("ANONADDY_TOKEN", "test-token");
-        std::env::set_var("ANONADDY_HOST", "https://my-anonaddy-instance.com");
+        let host = "https://my-addy-instance.com".to_string();
 
-        let anonaddy = AnonAddy::new(&client);
+        let anonaddy = AnonAddy::new(&client, Some(Secret::new("test-token")), Some(host)).unwrap();
 
         assert_eq!(anonaddy.client as *const _, &client as *const _);
-        assert_eq!(anonaddy.token, "test-token");
-        assert_eq!(
-            anonaddy.host,
-            "https://my-anonaddy-instance.com".to_string()
-        );
+        assert_eq!(anonaddy.token.expose(), "test-token");
+        assert_eq!(anonaddy.host, "https://my-addy-instance.com".to_string());
     }
 
     #[tokio::test]
@@ -231,17 +845,17 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: "https://localhost".to_string(),
         };
 
-        let response = anonaddy.get_aliases().await;
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &reqwest::Error = match error.downcast_ref::<reqwest::Error>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an reqwest::Error!"),
+        let actual_error: &reqwest::Error = match &error {
+            ApiError::Network { source } => source,
+            _ => panic!("Error returned was not an ApiError::Network!"),
         };
         assert!(actual_error.is_request());
     }
@@ -257,19 +871,49 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
-        let response = anonaddy.get_aliases().await;
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &AliasError = match error.downcast_ref::<AliasError>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an AliasError!"),
+        assert!(matches!(
+            error,
+            ApiError::UnexpectedStatus { status: 400, .. }
+        ));
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_aliases_returns_unauthorized_for_401() {
+        let server = MockServer::start();
+        let aliases_mock = server.mock(|when, then| {
+            when.method(GET).path("/api/v1/aliases");
+            then.status(401)
+                .header("content-type", "application/json")
+                .body(r#"{"message":"Unauthenticated."}"#);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
         };
-        assert_eq!(actual_error.message, "Failed to get aliases.");
+
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
+
+        let error = response.unwrap_err();
+        match &error {
+            ApiError::Unauthorized { status, body } => {
+                assert_eq!(*status, 401);
+                assert_eq!(body, r#"{"message":"Unauthenticated."}"#);
+            }
+            _ => panic!("Error returned was not an ApiError::Unauthorized!"),
+        }
 
         aliases_mock.assert();
     }
@@ -285,19 +929,15 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
-        let response = anonaddy.get_aliases().await;
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &reqwest::Error = match error.downcast_ref::<reqwest::Error>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an reqwest::Error!"),
-        };
-        assert!(actual_error.is_decode());
+        assert!(matches!(error, ApiError::Decode { status: 200, .. }));
 
         aliases_mock.assert();
     }
@@ -316,11 +956,11 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
-        let response = anonaddy.get_aliases().await;
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         let aliases = response.unwrap();
         assert_eq!(aliases.len(), 1);
@@ -348,11 +988,11 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
-        let response = anonaddy.get_aliases().await;
+        let response = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         let aliases = response.unwrap();
         assert_eq!(aliases.len(), 1);
@@ -380,11 +1020,11 @@ mod tests {
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
-        let aliases = anonaddy.get_aliases().await;
+        let aliases = anonaddy.get_aliases(&AliasQuery::default()).await;
 
         assert_eq!(aliases.unwrap().len(), 2);
 
@@ -392,76 +1032,235 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn deactivate_alias_returns_error_for_no_response() {
-        let alias_id = "test-id";
+    async fn get_aliases_skips_aliases_that_cannot_be_read() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_partial_aliases.json");
+            when.method(GET).path("/api/v1/aliases");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
 
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
-            host: "http://localhost".to_string(),
+            token: Secret::new("test-token"),
+            host: server.url(""),
         };
 
-        let response = anonaddy.deactivate_alias(alias_id).await;
+        let aliases = anonaddy.get_aliases(&AliasQuery::default()).await.unwrap();
 
-        assert!(response.is_err());
-        let error = response.unwrap_err();
-        let actual_error: &reqwest::Error = match error.downcast_ref::<reqwest::Error>() {
-            Some(error) => error,
-            None => panic!("Error returned was not a reqwest::Error!"),
-        };
-        assert!(actual_error.is_request());
+        let emails: Vec<&str> = aliases.iter().map(|alias| alias.get_email()).collect();
+        assert_eq!(
+            emails,
+            vec!["busy@johndoe.anonaddy.com", "minimal@johndoe.anonaddy.com"]
+        );
     }
 
     #[tokio::test]
-    async fn deactivate_alias_returns_error_if_status_200() {
+    async fn get_aliases_sends_filters_and_matches_domains() {
         let server = MockServer::start();
-
-        let alias_id = "test-id";
-        let aliases_mock = server.mock(|when, then| {
-            when.method(DELETE)
-                .path(format!("/api/v1/active-aliases/{}", &alias_id));
-            then.status(200).header("content-type", "application/json");
-        });
+        for (path, fixture) in [
+            ("/api/v1/usernames", "anonaddy_usernames.json"),
+            ("/api/v1/domains", "anonaddy_domains.json"),
+        ] {
+            server.mock(|when, then| {
+                let response = std::fs::read_to_string(format!("resources/test/{}", fixture));
+                when.method(GET).path(path);
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .body(response.unwrap());
+            });
+        }
+        let aliases_mock = |domain_filter: Option<(&str, &str)>| {
+            server.mock(|when, then| {
+                let response =
+                    std::fs::read_to_string("resources/test/anonaddy_multiple_aliases.json");
+                let when = when
+                    .method(GET)
+                    .path("/api/v1/aliases")
+                    .query_param("filter[active]", "true")
+                    .query_param("filter[deleted]", "with")
+                    .query_param("filter[search]", "shopping");
+                match domain_filter {
+                    Some((name, id)) => when.query_param(name, id),
+                    None => when
+                        .query_param_missing("domain")
+                        .query_param_missing("username"),
+                };
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .body(response.unwrap());
+            })
+        };
+        let unfiltered_mock = aliases_mock(None);
+        let domain_mock = aliases_mock(Some(("domain", "0ad7a75a-1517-4b74-bb2a-8e2f5a9b1a6d")));
+        let username_mock =
+            aliases_mock(Some(("username", "2a0b4b34-7a2a-4e57-8a10-54e8b0d3a4c5")));
 
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
-
-        let response = anonaddy.deactivate_alias(alias_id).await;
-
-        assert!(response.is_err());
-        let error = response.unwrap_err();
-        let actual_error: &AliasError = match error.downcast_ref::<AliasError>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an AliasError!"),
+        let mut query = AliasQuery {
+            active: Some(true),
+            deleted: DeletedAliases::Include,
+            search: Some("shopping".to_string()),
+            domains: vec!["JohnDoe.anonaddy.com".to_string()],
         };
-        assert_eq!(
-            actual_error.message,
-            format!("Failed to deactivate alias {}.", alias_id)
-        );
 
-        aliases_mock.assert();
+        // A shared domain has no ID to filter on, so its aliases are matched once listed.
+        let aliases = anonaddy.get_aliases(&query).await;
+        assert_eq!(aliases.unwrap().len(), 2);
+        unfiltered_mock.assert_calls(1);
+
+        query.domains = vec!["example.com".to_string(), "JohnDoe.127.0.0.1".to_string()];
+        let aliases = anonaddy.get_aliases(&query).await;
+        assert!(aliases.unwrap().is_empty());
+        domain_mock.assert_calls(1);
+        username_mock.assert_calls(1);
+        unfiltered_mock.assert_calls(1);
     }
 
     #[tokio::test]
-    async fn deactivate_alias_returns_ok() {
+    async fn get_domains_returns_usernames_and_custom_domains() {
         let server = MockServer::start();
-
-        let alias_id = "test-id";
-        let aliases_mock = server.mock(|when, then| {
-            when.method(DELETE)
-                .path(format!("/api/v1/active-aliases/{}", &alias_id));
-            then.status(204).header("content-type", "application/json");
+        let usernames_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_usernames.json");
+            when.method(GET).path("/api/v1/usernames");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+        let domains_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_domains.json");
+            when.method(GET).path("/api/v1/domains");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
         });
 
         let client = reqwest::Client::new();
         let anonaddy = AnonAddy {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let domains = anonaddy.get_domains().await.unwrap();
+
+        assert_eq!(
+            domains,
+            vec![
+                AliasDomain {
+                    id: "2a0b4b34-7a2a-4e57-8a10-54e8b0d3a4c5".to_string(),
+                    domain: "johndoe.127.0.0.1".to_string(),
+                    kind: DomainKind::Username,
+                    active: true,
+                    catch_all: true,
+                },
+                AliasDomain {
+                    id: "0ad7a75a-1517-4b74-bb2a-8e2f5a9b1a6d".to_string(),
+                    domain: "example.com".to_string(),
+                    kind: DomainKind::Custom,
+                    active: true,
+                    catch_all: false,
+                },
+            ]
+        );
+
+        usernames_mock.assert();
+        domains_mock.assert();
+    }
+
+    #[test]
+    fn username_domain_drops_app_prefix() {
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: "https://app.example.com".to_string(),
+        };
+
+        assert_eq!(anonaddy.username_domain(), "example.com");
+
+        let addy = AnonAddy {
+            host: DEFAULT_HOST.to_string(),
+            ..anonaddy
+        };
+        assert_eq!(addy.username_domain(), "anonaddy.com");
+    }
+
+    #[tokio::test]
+    async fn deactivate_alias_returns_error_for_no_response() {
+        let alias_id = "test-id";
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: "http://localhost".to_string(),
+        };
+
+        let response = anonaddy.deactivate_alias(alias_id).await;
+
+        assert!(response.is_err());
+        let error = response.unwrap_err();
+        let actual_error: &reqwest::Error = match &error {
+            ApiError::Network { source } => source,
+            _ => panic!("Error returned was not an ApiError::Network!"),
+        };
+        assert!(actual_error.is_request());
+    }
+
+    #[tokio::test]
+    async fn deactivate_alias_returns_error_if_status_200() {
+        let server = MockServer::start();
+
+        let alias_id = "test-id";
+        let aliases_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path(format!("/api/v1/active-aliases/{}", &alias_id));
+            then.status(200).header("content-type", "application/json");
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy.deactivate_alias(alias_id).await;
+
+        assert!(response.is_err());
+        let error = response.unwrap_err();
+        assert!(matches!(
+            error,
+            ApiError::UnexpectedStatus { status: 200, .. }
+        ));
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn deactivate_alias_returns_ok() {
+        let server = MockServer::start();
+
+        let alias_id = "test-id";
+        let aliases_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path(format!("/api/v1/active-aliases/{}", &alias_id));
+            then.status(204).header("content-type", "application/json");
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
@@ -471,4 +1270,580 @@ mod tests {
 
         aliases_mock.assert();
     }
+
+    #[tokio::test]
+    async fn reactivate_alias_returns_not_found() {
+        let server = MockServer::start();
+
+        let aliases_mock = server.mock(|when, then| {
+            when.method(POST).path("/api/v1/active-aliases");
+            then.status(404).header("content-type", "application/json");
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy.reactivate_alias("test-id").await;
+
+        assert!(matches!(
+            response.unwrap_err(),
+            ApiError::NotFound { status: 404, .. }
+        ));
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn reactivate_alias_returns_ok() {
+        let server = MockServer::start();
+
+        let aliases_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_active_alias.json");
+            when.method(POST)
+                .path("/api/v1/active-aliases")
+                .header("Authorization", "Bearer test-token")
+                .json_body_includes(r#"{"id": "test-id"}"#);
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy.reactivate_alias("test-id").await;
+
+        assert!(response.is_ok());
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn delete_alias_returns_ok() {
+        let server = MockServer::start();
+
+        let aliases_mock = server.mock(|when, then| {
+            when.method(DELETE).path("/api/v1/aliases/test-id");
+            then.status(204);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy.delete_alias("test-id").await;
+
+        assert!(response.is_ok());
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn set_description_returns_ok() {
+        let server = MockServer::start();
+
+        let aliases_mock = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/api/v1/aliases/test-id")
+                .json_body_includes(r#"{"description": "[PWNED Adobe] Shopping"}"#);
+            then.status(200).header("content-type", "application/json");
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy
+            .set_description("test-id", "[PWNED Adobe] Shopping")
+            .await;
+
+        assert!(response.is_ok());
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_alias_returns_alias() {
+        let server = MockServer::start();
+
+        let alias_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_alias.json");
+            when.method(GET).path("/api/v1/aliases/test-id");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let alias = anonaddy.get_alias("test-id").await.unwrap();
+
+        assert_eq!(alias.get_id(), "test-id");
+        assert_eq!(
+            alias.get_email(),
+            "marvelous.notebook25@johndoe.anonaddy.com"
+        );
+        alias_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_alias_returns_not_found_error() {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v1/aliases/unknown-id");
+            then.status(404);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy.get_alias("unknown-id").await;
+
+        assert_eq!(response.err().unwrap().status(), Some(404));
+    }
+
+    #[tokio::test]
+    async fn create_replacement_returns_new_alias() {
+        let server = MockServer::start();
+
+        let alias_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_alias.json");
+            when.method(GET).path("/api/v1/aliases/test-id");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+        let aliases_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_created_alias.json");
+            when.method(POST)
+                .path("/api/v1/aliases")
+                .json_body_includes(
+                    r#"{
+                        "domain": "johndoe.anonaddy.com",
+                        "description": "Shopping",
+                        "format": "random_words",
+                        "recipient_ids": ["46eebc50-f7f8-46d7-beb9-c37f04c29a84"]
+                    }"#,
+                );
+            then.status(201)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+        let old = FakeAlias {
+            description: Some("Shopping".to_string()),
+            ..FakeAlias::new("test-id", "first@johndoe.anonaddy.com")
+        };
+
+        let response = anonaddy.create_replacement(&old).await;
+
+        let replacement = response.unwrap();
+        assert_eq!(replacement.get_email(), "f3d8a2c1@johndoe.anonaddy.com");
+        assert_eq!(replacement.get_description(), Some("Shopping"));
+
+        alias_mock.assert();
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn create_blocked_alias_creates_alias_without_deactivating_it() {
+        let server = MockServer::start();
+        let create_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_created_alias.json");
+            when.method(POST)
+                .path("/api/v1/aliases")
+                .json_body_includes(
+                    r#"{
+                    "domain": "johndoe.anonaddy.com",
+                    "format": "custom",
+                    "local_part": "f3d8a2c1"
+                }"#,
+                );
+            then.status(201)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+        let deactivate_mock = server.mock(|when, then| {
+            when.method(DELETE).path_includes("/api/v1/active-aliases/");
+            then.status(204);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+        let domain = AliasDomain {
+            id: "username-id".to_string(),
+            domain: "johndoe.anonaddy.com".to_string(),
+            kind: DomainKind::Username,
+            active: true,
+            catch_all: true,
+        };
+
+        let blocked = anonaddy
+            .create_blocked_alias(&domain, "f3d8a2c1")
+            .await
+            .unwrap();
+
+        assert_eq!(blocked.get_email(), "f3d8a2c1@johndoe.anonaddy.com");
+        create_mock.assert();
+        deactivate_mock.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn disable_catch_all_uses_endpoint_for_domain_kind() {
+        let server = MockServer::start();
+        let usernames_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path("/api/v1/catch-all-usernames/username-id");
+            then.status(204);
+        });
+        let domains_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path("/api/v1/catch-all-domains/domain-id");
+            then.status(204);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+        let username = AliasDomain {
+            id: "username-id".to_string(),
+            domain: "johndoe.anonaddy.com".to_string(),
+            kind: DomainKind::Username,
+            active: true,
+            catch_all: true,
+        };
+        let domain = AliasDomain {
+            id: "domain-id".to_string(),
+            domain: "example.com".to_string(),
+            kind: DomainKind::Custom,
+            ..username.clone()
+        };
+
+        anonaddy.disable_catch_all(&username).await.unwrap();
+        anonaddy.disable_catch_all(&domain).await.unwrap();
+
+        usernames_mock.assert();
+        domains_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn create_replacement_returns_error_if_not_created() {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_alias.json");
+            when.method(GET).path("/api/v1/aliases/test-id");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+        let aliases_mock = server.mock(|when, then| {
+            when.method(POST).path("/api/v1/aliases");
+            then.status(403)
+                .body("You have reached your active alias limit");
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = anonaddy
+            .create_replacement(&FakeAlias::new("test-id", "first@johndoe.anonaddy.com"))
+            .await;
+
+        assert!(matches!(
+            response.err().unwrap(),
+            ApiError::Forbidden { status: 403, .. }
+        ));
+
+        aliases_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_account_summary_returns_limits() {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v1/app-version");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"version": "1.3.2", "major": 1, "minor": 3, "patch": 2}"#);
+        });
+
+        let account_mock = server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_account.json");
+            when.method(GET)
+                .path("/api/v1/account-details")
+                .header("Authorization", "Bearer test-token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let account = anonaddy.get_account_summary().await.unwrap();
+
+        assert_eq!(
+            account,
+            AccountSummary {
+                provider: "addy.io".to_string(),
+                version: Some("1.3.2".to_string()),
+                username: "johndoe".to_string(),
+                subscription: Some("free".to_string()),
+                alias_count: 12,
+                alias_limit: Some(20),
+                username_count: 1,
+                username_limit: Some(3),
+            }
+        );
+
+        account_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_version_returns_none_for_older_instances() {
+        let server = MockServer::start();
+
+        let version_mock = server.mock(|when, then| {
+            when.method(GET).path("/api/v1/app-version");
+            then.status(404);
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        assert_eq!(anonaddy.get_version().await.unwrap(), None);
+        version_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_account_summary_returns_limits_when_version_fails() {
+        let server = MockServer::start();
+
+        let version_mock = server.mock(|when, then| {
+            when.method(GET).path("/api/v1/app-version");
+            then.status(500);
+        });
+
+        server.mock(|when, then| {
+            let response = std::fs::read_to_string("resources/test/anonaddy_account.json");
+            when.method(GET).path("/api/v1/account-details");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(response.unwrap());
+        });
+
+        let client = reqwest::Client::new();
+        let anonaddy = AnonAddy {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let account = anonaddy.get_account_summary().await.unwrap();
+
+        assert_eq!(account.version, None);
+        assert_eq!(account.username, "johndoe");
+        version_mock.assert();
+    }
+
+    #[test]
+    fn alias_decodes_old_and_new_shapes() {
+        let json = std::fs::read_to_string("resources/test/anonaddy_alias.json").unwrap();
+        let old = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+        let json = std::fs::read_to_string("resources/test/addy_alias.json").unwrap();
+        let new = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+
+        assert_eq!(old.from_name, None);
+        assert_eq!(old.last_forwarded, None);
+        assert_eq!(new.email, old.email);
+        assert_eq!(new.from_name.as_deref(), Some("John Doe"));
+        assert_eq!(
+            new.last_forwarded,
+            Some("2024-05-01T09:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn alias_reads_times_in_other_formats() {
+        let mut json: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/test/addy_alias.json").unwrap(),
+        )
+        .unwrap();
+        json["data"]["created_at"] = "2024-01-01T00:00:00.000000Z".into();
+        json["data"]["updated_at"] = "yesterday".into();
+
+        let alias = serde_json::from_value::<AnonAddyItemResponse<AnonAddyAlias>>(json)
+            .unwrap()
+            .data;
+
+        assert_eq!(
+            alias.created_at,
+            Some("2024-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(alias.updated_at, None);
+        assert_eq!(
+            alias.last_forwarded,
+            Some("2024-05-01T09:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn alias_serialises_in_the_shape_it_is_read() {
+        let json = std::fs::read_to_string("resources/test/addy_alias.json").unwrap();
+        let alias = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+
+        let exported = serde_json::to_value(&alias).unwrap();
+        let imported: AnonAddyAlias = serde_json::from_value(exported.clone()).unwrap();
+
+        assert_eq!(
+            exported["email"],
+            "marvelous.notebook25@johndoe.anonaddy.com"
+        );
+        assert_eq!(exported["recipients"][0]["email"], "me@example.com");
+        assert_eq!(exported["from_name"], "John Doe");
+        assert_eq!(exported["created_at"], "2019-10-01 09:00:00");
+        assert_eq!(format!("{:?}", imported), format!("{:?}", alias));
+    }
+
+    #[test]
+    fn info_describes_alias() {
+        let json = std::fs::read_to_string("resources/test/anonaddy_alias.json").unwrap();
+        let alias = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+
+        let info = alias.info();
+
+        assert_eq!(info.provider, "addy.io");
+        assert_eq!(info.domain, "johndoe.anonaddy.com");
+        assert_eq!(info.recipients, vec!["me@example.com"]);
+        assert_eq!(
+            info.created_at,
+            Some("2019-10-01T09:00:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            info.counters,
+            AliasCounters {
+                forwarded: 5,
+                ..Default::default()
+            }
+        );
+        assert_eq!(info.info(), info);
+        let exported = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<AliasInfo>(&exported).unwrap(), info);
+        assert_eq!(info.get_email(), alias.get_email());
+    }
+
+    #[test]
+    fn format_is_inferred_from_local_part() {
+        let json = std::fs::read_to_string("resources/test/anonaddy_alias.json").unwrap();
+        let mut alias = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+        assert_eq!(alias.format(), Some("random_words"));
+
+        alias.local_part = "f3d8a2c1".to_string();
+        assert_eq!(alias.format(), Some("random_characters"));
+
+        alias.local_part = alias.id.clone();
+        assert_eq!(alias.format(), Some("uuid"));
+
+        alias.local_part = "shop-2024".to_string();
+        assert_eq!(alias.format(), None);
+    }
+
+    #[test]
+    fn format_is_none_for_custom_local_parts() {
+        let json = std::fs::read_to_string("resources/test/anonaddy_alias.json").unwrap();
+        let mut alias = serde_json::from_str::<AnonAddyItemResponse<AnonAddyAlias>>(&json)
+            .unwrap()
+            .data;
+
+        for local_part in [
+            "john.doe",
+            "shopping",
+            "john.doe.2024",
+            "John.Doe25",
+            "shop.25",
+            "abcd1234x",
+        ] {
+            alias.local_part = local_part.to_string();
+            assert_eq!(alias.format(), None, "{}", local_part);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn debug_and_display_mask_email_in_privacy_mode() {
+        let json = std::fs::read_to_string("resources/test/anonaddy_active_alias.json").unwrap();
+        let response: AnonAddyResponse<AnonAddyAlias> = serde_json::from_str(&json).unwrap();
+        let alias: Box<dyn Alias> = Box::new(response.data.into_iter().next().unwrap());
+
+        redact::set_privacy_mode(true);
+        let debug = format!("{:?}", alias);
+        let display = alias.to_string();
+        redact::set_privacy_mode(false);
+
+        assert!(!debug.contains("first"));
+        assert!(debug.contains("\"f***@johndoe.anonaddy.com\""));
+        assert_eq!(display, "f***@johndoe.anonaddy.com - ");
+        assert_eq!(alias.to_string(), "first@johndoe.anonaddy.com - ");
+    }
 }
@@ -0,0 +1,288 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+
+use crate::logging::{LevelFilters, LogFormat};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML configuration file to read, defaulting to `has-my-alias-been-pwned.toml` if it exists.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// File to keep the results of each check in, along with any acknowledged breaches.
+    #[arg(long, global = true, default_value = "has-my-alias-been-pwned.json")]
+    pub state_file: PathBuf,
+    /// Mask email addresses and URLs in log output, so logs can be shipped somewhere shared.
+    #[arg(long, global = true)]
+    pub privacy: bool,
+    /// Level to log at, overriding `RUST_LOG` and the configuration, e.g. `debug` or `warn,has_my_alias_been_pwned=debug`.
+    #[arg(long, global = true)]
+    pub log_level: Option<LevelFilters>,
+    /// How to write each log record.
+    #[arg(long, global = true, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// File to log to instead of stdout, rolled over once it reaches the configured size.
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check all aliases once and exit (the default).
+    Scan,
+    /// Keep running, checking all aliases on a schedule.
+    #[command(alias = "daemon")]
+    Serve(ServeArgs),
+    /// Reactivate the aliases deactivated by a previous run, for when a breach turns out to be a false positive.
+    Undo(UndoArgs),
+    /// Acknowledge breaches of an alias that have been dealt with, so scans no longer act on or alert about them.
+    #[command(alias = "ack")]
+    Acknowledge(AcknowledgeArgs),
+    /// Work with the configuration file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+impl Command {
+    /// Whether the command only reads or writes the state file, so needs neither the configuration nor the services.
+    pub fn uses_only_state(&self) -> bool {
+        matches!(
+            self,
+            Command::Acknowledge(_) | Command::Undo(UndoArgs { list: true, .. })
+        )
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Check the configuration, reporting every problem found.
+    Validate,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// How long to wait between scans (e.g. `30m`, `12h`, `1day`), defaulting to the configured schedule or `24h`.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub interval: Option<Duration>,
+    /// Cron expression (`sec min hour day-of-month month day-of-week`, in UTC) to scan on instead of an interval.
+    #[arg(long, conflicts_with = "interval")]
+    pub cron: Option<String>,
+    /// Address to serve the dashboard and HTTP endpoints (e.g. `/metrics`) on, such as `127.0.0.1:9090`.
+    #[arg(long)]
+    pub listen: Option<SocketAddr>,
+}
+
+#[derive(Args, Debug)]
+pub struct UndoArgs {
+    /// When the run to undo started, as listed by `--list`, defaulting to the most recent run with deactivations.
+    #[arg(long)]
+    pub run: Option<DateTime<Utc>>,
+    /// List the runs with deactivations that can be undone, rather than undoing one.
+    #[arg(long, conflicts_with = "run")]
+    pub list: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AcknowledgeArgs {
+    /// The ID or email address of the alias.
+    #[arg(required_unless_present = "list")]
+    pub alias: Option<String>,
+    /// Names of the breaches, as shown in the report, defaulting to every breach currently found for the alias.
+    pub breaches: Vec<String>,
+    /// Remove the acknowledgements instead, so the breaches are acted on again.
+    #[arg(long)]
+    pub remove: bool,
+    /// List the acknowledged breaches of every alias, or of just the one given.
+    #[arg(long, conflicts_with = "remove")]
+    pub list: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_defaults_to_no_command() {
+        let cli = Cli::try_parse_from(["has-my-alias-been-pwned"]).unwrap();
+
+        assert!(cli.command.is_none());
+        assert!(!cli.privacy);
+    }
+
+    #[test]
+    fn uses_only_state_for_acknowledging_and_listing_undoable_runs() {
+        let uses_only_state = |args: &[&str]| {
+            Cli::try_parse_from([&["has-my-alias-been-pwned"], args].concat())
+                .unwrap()
+                .command
+                .unwrap()
+                .uses_only_state()
+        };
+
+        assert!(uses_only_state(&["ack", "shop@example.com", "Adobe"]));
+        assert!(uses_only_state(&["ack", "--list"]));
+        assert!(uses_only_state(&["undo", "--list"]));
+        assert!(!uses_only_state(&["undo"]));
+        assert!(!uses_only_state(&["scan"]));
+    }
+
+    #[test]
+    fn parse_scan_with_privacy() {
+        let cli = Cli::try_parse_from(["has-my-alias-been-pwned", "scan", "--privacy"]).unwrap();
+
+        assert!(matches!(cli.command, Some(Command::Scan)));
+        assert!(cli.privacy);
+    }
+
+    #[test]
+    fn parse_logging_options() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "--log-level",
+            "warn,has_my_alias_been_pwned=debug",
+            "--log-format",
+            "json",
+            "--log-file",
+            "app.log",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.log_level.unwrap().root, log::LevelFilter::Warn);
+        assert_eq!(cli.log_format, Some(LogFormat::Json));
+        assert_eq!(cli.log_file, Some(PathBuf::from("app.log")));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_log_level() {
+        assert!(Cli::try_parse_from(["has-my-alias-been-pwned", "--log-level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn parse_serve_with_interval() {
+        let cli =
+            Cli::try_parse_from(["has-my-alias-been-pwned", "serve", "--interval", "12h"]).unwrap();
+
+        match cli.command {
+            Some(Command::Serve(args)) => {
+                assert_eq!(args.interval, Some(Duration::from_secs(12 * 60 * 60)));
+                assert!(args.cron.is_none());
+                assert!(args.listen.is_none());
+            }
+            _ => panic!("Expected serve command"),
+        }
+    }
+
+    #[test]
+    fn parse_daemon_alias_with_cron() {
+        let cli =
+            Cli::try_parse_from(["has-my-alias-been-pwned", "daemon", "--cron", "0 0 3 * * *"])
+                .unwrap();
+
+        match cli.command {
+            Some(Command::Serve(args)) => assert_eq!(args.cron.as_deref(), Some("0 0 3 * * *")),
+            _ => panic!("Expected serve command"),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_interval_and_cron_together() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "serve",
+            "--interval",
+            "12h",
+            "--cron",
+            "0 0 3 * * *",
+        ]);
+
+        assert!(cli.is_err());
+    }
+
+    #[test]
+    fn parse_serve_with_listen_address() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "serve",
+            "--listen",
+            "127.0.0.1:9090",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Serve(args)) => {
+                assert_eq!(args.listen, Some("127.0.0.1:9090".parse().unwrap()))
+            }
+            _ => panic!("Expected serve command"),
+        }
+    }
+
+    #[test]
+    fn parse_undo_with_run() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "undo",
+            "--run",
+            "2024-05-01T03:00:00Z",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Undo(args)) => {
+                assert_eq!(args.run, Some("2024-05-01T03:00:00Z".parse().unwrap()));
+                assert!(!args.list);
+            }
+            _ => panic!("Command was not undo!"),
+        }
+    }
+
+    #[test]
+    fn parse_acknowledge_with_breaches() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "ack",
+            "email@email.com",
+            "Adobe",
+            "LinkedIn",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Acknowledge(args)) => {
+                assert_eq!(args.alias.as_deref(), Some("email@email.com"));
+                assert_eq!(args.breaches, vec!["Adobe", "LinkedIn"]);
+                assert!(!args.remove);
+            }
+            _ => panic!("Command was not acknowledge!"),
+        }
+    }
+
+    #[test]
+    fn parse_acknowledge_requires_alias_unless_listing() {
+        assert!(Cli::try_parse_from(["has-my-alias-been-pwned", "acknowledge"]).is_err());
+        assert!(Cli::try_parse_from(["has-my-alias-been-pwned", "acknowledge", "--list"]).is_ok());
+    }
+
+    #[test]
+    fn parse_config_validate_with_config_file() {
+        let cli = Cli::try_parse_from([
+            "has-my-alias-been-pwned",
+            "config",
+            "validate",
+            "--config",
+            "config.toml",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Some(Command::Config(ConfigCommand::Validate))
+        ));
+        assert_eq!(cli.config, Some(PathBuf::from("config.toml")));
+    }
+}
@@ -0,0 +1,1185 @@
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::anonaddy;
+use crate::daemon::Schedule;
+use crate::email_alias::AliasQuery;
+use crate::filter::{self, AliasFilter, AliasRule};
+use crate::logging::{self, FileLog, LevelFilters, LogFormat, LoggingSettings};
+use crate::notify::Notifier;
+use crate::report::OutputFormat;
+use crate::scan::{ActionOverride, BreachAction, Policy, RetryPolicy};
+use crate::secret::{Secret, SecretSource};
+
+/// The file read for configuration when one is not given explicitly.
+pub const DEFAULT_PATH: &str = "has-my-alias-been-pwned.toml";
+
+/// Configuration as read from the TOML file.
+///
+/// Values that need interpreting are kept as strings here, so that [`Config::resolve`] can report every problem at
+/// once rather than stopping at the first.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub hibp: HibpConfig,
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+    #[serde(default)]
+    pub aliases: AliasesConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub dashboard: DashboardConfig,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HibpConfig {
+    pub token: Option<SecretSource>,
+    /// Whether to search the catch-all domains for breached addresses, which needs them verified with HIBP.
+    pub domain_search: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProvidersConfig {
+    /// addy.io, or a self-hosted instance, also read from `[providers.anonaddy]` as named before the rebrand.
+    #[serde(alias = "anonaddy")]
+    pub addy: Option<AddyConfig>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AddyConfig {
+    pub token: Option<SecretSource>,
+    pub host: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AliasesConfig {
+    /// Only list aliases whose email address or description contains this text.
+    pub search: Option<String>,
+    /// Only list aliases on these domains.
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Whether to list deleted aliases, one of `exclude` (the default), `include` or `only`.
+    pub deleted: Option<String>,
+    /// Whether to check inactive (and any deleted) aliases too, only reporting them if breached.
+    pub check_inactive: Option<bool>,
+    /// If given, only aliases matching one of these rules are scanned.
+    #[serde(default)]
+    pub include: Vec<AliasRuleConfig>,
+    /// Aliases matching any of these rules are never scanned or acted on.
+    #[serde(default)]
+    pub exclude: Vec<AliasRuleConfig>,
+}
+
+/// Matches aliases on every one of the fields given.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AliasRuleConfig {
+    pub id: Option<String>,
+    /// A glob for the email address, e.g. `team-*@example.com`.
+    pub email: Option<String>,
+    pub email_regex: Option<String>,
+    pub domain: Option<String>,
+    /// Text the description contains.
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// What to do with a breached alias, one of `deactivate` (the default), `delete`, `tag`, `replace` or `report`.
+    pub action: Option<String>,
+    /// Actions for specific aliases, overriding `action`.
+    #[serde(default)]
+    pub overrides: Vec<ActionOverrideConfig>,
+    /// What to do when a domain search finds a breached address with no alias, one of `report` (the default), `block`
+    /// or `disable_catch_all`.
+    pub unknown_addresses: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ActionOverrideConfig {
+    /// The ID or email address of the alias.
+    pub alias: String,
+    pub action: String,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NotifierConfig {
+    /// The kind of notifier, currently only `webhook`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// How to write the report of each scan, either `text` (the default) or `json`.
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub interval: Option<String>,
+    pub cron: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// How many times a request that failed for a transient reason is made in total, at least 1.
+    pub attempts: Option<u32>,
+    /// How long to wait before the first retry, doubling for each retry after that.
+    pub delay: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DashboardConfig {
+    /// Hosts the dashboard is reached at besides its listen address, such as one a reverse proxy passes on, with the
+    /// port unless it is the default for the scheme.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// The level to log at, in the same form as `RUST_LOG`, e.g. `info` (the default) or
+    /// `warn,has_my_alias_been_pwned=debug`.
+    pub level: Option<String>,
+    /// How to write each log record, either `plain` (the default) or `json`.
+    pub format: Option<String>,
+    /// A file to log to instead of stdout, rolled over once it reaches `max_size`.
+    pub file: Option<PathBuf>,
+    pub max_size: Option<String>,
+    /// How many rolled over log files to keep.
+    pub keep: Option<u32>,
+    /// Whether to mask email addresses and URLs in log output.
+    pub privacy: Option<bool>,
+}
+
+/// Configuration once it has been validated.
+#[derive(Debug)]
+pub struct Settings {
+    /// Which aliases to list, before the include and exclude rules are applied.
+    pub query: AliasQuery,
+    /// Whether inactive and deleted aliases are checked as well as active ones.
+    pub check_inactive: bool,
+    /// Whether the catch-all domains are searched for breached addresses with no alias.
+    pub domain_search: bool,
+    pub filter: AliasFilter,
+    pub policy: Policy,
+    pub output_format: OutputFormat,
+    pub notifiers: Vec<Notifier>,
+    pub schedule: Option<Schedule>,
+    pub retry: RetryPolicy,
+    pub logging: LoggingSettings,
+    /// Hosts the dashboard is reached at besides its listen address.
+    pub dashboard_hosts: Vec<String>,
+}
+
+impl Config {
+    /// Reads the configuration from the given file.
+    ///
+    /// If no file is given, [`DEFAULT_PATH`] is used if it exists, otherwise the configuration is empty.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).exists() => Path::new(DEFAULT_PATH),
+            None => return Ok(Config::default()),
+        };
+        let unreadable = |reason: String| {
+            ConfigError::new(vec![Problem::Unreadable {
+                file: path.display().to_string(),
+                reason,
+            }])
+        };
+        let contents =
+            std::fs::read_to_string(path).map_err(|error| unreadable(error.to_string()))?;
+        contents
+            .parse()
+            .map_err(|error: toml::de::Error| unreadable(error.to_string()))
+    }
+
+    /// Reads the tokens for the services from where they are kept, along with the addy.io host.
+    ///
+    /// Like a `.env` file, environment variables take precedence, so they can be used to override the configuration.
+    /// A token can also be read from the file named by the variable with a `_FILE` suffix, such as `HIBP_TOKEN_FILE`,
+    /// for Docker and Kubernetes secrets. Tokens are only kept in memory and passed to the services, never written to
+    /// the environment where commands run by the tool would inherit them.
+    ///
+    /// Tokens that could not be read are left out of the credentials, and every one of them is reported in the error.
+    pub async fn read_credentials(&self) -> (Credentials, Option<ConfigError>) {
+        let mut problems = vec![];
+        let addy = self.providers.addy.as_ref();
+        let hibp_token = read_token(
+            "hibp.token",
+            "HIBP_TOKEN",
+            |name| std::env::var_os(name),
+            self.hibp.token.as_ref(),
+        )
+        .await
+        .unwrap_or_else(|problem| {
+            problems.push(problem);
+            None
+        });
+        let addy_token = read_token(
+            "providers.addy.token",
+            "ADDY_TOKEN",
+            anonaddy::var_os,
+            addy.and_then(|addy| addy.token.as_ref()),
+        )
+        .await
+        .unwrap_or_else(|problem| {
+            problems.push(problem);
+            None
+        });
+        let addy_host = anonaddy::var_os("ADDY_HOST")
+            .and_then(|host| host.into_string().ok())
+            .or_else(|| addy.and_then(|addy| addy.host.clone()));
+        let credentials = Credentials {
+            hibp_token,
+            addy_token,
+            addy_host,
+        };
+        let error = (!problems.is_empty()).then(|| ConfigError::new(problems));
+        (credentials, error)
+    }
+
+    /// Validates the configuration, returning every problem found.
+    ///
+    /// The tokens and hosts are validated when the services are created, see [`ConfigError::collect`] for combining
+    /// them with these problems.
+    pub fn resolve(&self) -> Result<Settings, ConfigError> {
+        let mut problems = vec![];
+
+        let mut query = AliasQuery {
+            search: self.aliases.search.clone(),
+            domains: self.aliases.domains.clone(),
+            ..Default::default()
+        };
+        if let Some(deleted) = &self.aliases.deleted {
+            match deleted.parse() {
+                Ok(deleted) => query.deleted = deleted,
+                Err(reason) => problems.push(Problem::invalid("aliases.deleted", deleted, &reason)),
+            }
+        }
+
+        let filter = AliasFilter {
+            include: resolve_rules("aliases.include", &self.aliases.include, &mut problems),
+            exclude: resolve_rules("aliases.exclude", &self.aliases.exclude, &mut problems),
+        };
+
+        let mut policy = Policy::default();
+        if let Some(action) = &self.policy.action {
+            match action.parse() {
+                Ok(action) => policy.action = action,
+                Err(reason) => problems.push(Problem::invalid("policy.action", action, &reason)),
+            }
+        }
+        for (index, rule) in self.policy.overrides.iter().enumerate() {
+            match rule.action.parse::<BreachAction>() {
+                Ok(action) => policy.overrides.push(ActionOverride {
+                    alias: rule.alias.clone(),
+                    action,
+                }),
+                Err(reason) => problems.push(Problem::invalid(
+                    &format!("policy.overrides[{}].action", index),
+                    &rule.action,
+                    &reason,
+                )),
+            }
+        }
+
+        if let Some(action) = &self.policy.unknown_addresses {
+            match action.parse() {
+                Ok(action) => policy.unknown_addresses = action,
+                Err(reason) => problems.push(Problem::invalid(
+                    "policy.unknown_addresses",
+                    action,
+                    &reason,
+                )),
+            }
+        }
+
+        let mut notifiers = vec![];
+        for (index, notifier) in self.notifiers.iter().enumerate() {
+            match notifier.kind.as_str() {
+                "webhook" => match notifier.url.as_deref() {
+                    Some(url) => match reqwest::Url::parse(url) {
+                        Ok(url) => notifiers.push(Notifier::Webhook { url }),
+                        Err(error) => problems.push(Problem::invalid(
+                            &format!("notifiers[{}].url", index),
+                            url,
+                            &format!("is not a valid URL: {}", error),
+                        )),
+                    },
+                    None => problems.push(Problem::Missing {
+                        setting: format!("notifiers[{}].url", index),
+                        variable: None,
+                    }),
+                },
+                kind => problems.push(Problem::invalid(
+                    &format!("notifiers[{}].type", index),
+                    kind,
+                    "must be \"webhook\"",
+                )),
+            }
+        }
+
+        let output_format = match self.output.format.as_deref() {
+            None | Some("text") => Some(OutputFormat::Text),
+            Some("json") => Some(OutputFormat::Json),
+            Some(format) => {
+                problems.push(Problem::invalid(
+                    "output.format",
+                    format,
+                    "must be one of \"text\" or \"json\"",
+                ));
+                None
+            }
+        };
+
+        let schedule = match (&self.schedule.interval, &self.schedule.cron) {
+            (Some(_), Some(_)) => {
+                problems.push(Problem::Conflict {
+                    settings: vec!["schedule.interval".to_string(), "schedule.cron".to_string()],
+                });
+                None
+            }
+            (Some(interval), None) => match humantime::parse_duration(interval) {
+                Ok(interval) => Some(Schedule::Interval(interval)),
+                Err(error) => {
+                    problems.push(Problem::invalid(
+                        "schedule.interval",
+                        interval,
+                        &format!("is not a valid duration: {}", error),
+                    ));
+                    None
+                }
+            },
+            (None, Some(cron)) => match Schedule::cron(cron) {
+                Ok(schedule) => Some(schedule),
+                Err(error) => {
+                    problems.push(Problem::invalid(
+                        "schedule.cron",
+                        cron,
+                        &format!("is not a valid cron expression: {}", error),
+                    ));
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+
+        let mut retry = RetryPolicy::default();
+        match self.retry.attempts {
+            Some(0) => problems.push(Problem::invalid(
+                "retry.attempts",
+                "0",
+                "must be at least 1",
+            )),
+            Some(attempts) => retry.attempts = attempts,
+            None => {}
+        }
+        if let Some(delay) = &self.retry.delay {
+            match humantime::parse_duration(delay) {
+                Ok(delay) => retry.delay = delay,
+                Err(error) => problems.push(Problem::invalid(
+                    "retry.delay",
+                    delay,
+                    &format!("is not a valid duration: {}", error),
+                )),
+            }
+        }
+
+        let logging = self.logging.resolve(&mut problems);
+
+        if !problems.is_empty() {
+            return Err(ConfigError::new(problems));
+        }
+        Ok(Settings {
+            query,
+            check_inactive: self.aliases.check_inactive.unwrap_or(false),
+            domain_search: self.hibp.domain_search.unwrap_or(false),
+            filter,
+            policy,
+            output_format: output_format.unwrap(),
+            notifiers,
+            schedule,
+            retry,
+            logging,
+            dashboard_hosts: self.dashboard.hosts.clone(),
+        })
+    }
+}
+
+/// Compiles the rules, reporting any that are empty or have an invalid pattern.
+fn resolve_rules(
+    setting: &str,
+    rules: &[AliasRuleConfig],
+    problems: &mut Vec<Problem>,
+) -> Vec<AliasRule> {
+    let mut resolved = vec![];
+    for (index, rule) in rules.iter().enumerate() {
+        let setting = format!("{}[{}]", setting, index);
+        if *rule == AliasRuleConfig::default() {
+            problems.push(Problem::invalid(
+                &setting,
+                "{}",
+                "must set at least one of id, email, email_regex, domain or description",
+            ));
+            continue;
+        }
+        let mut emails = vec![];
+        let patterns = [
+            ("email", &rule.email, filter::glob as fn(&str) -> _),
+            ("email_regex", &rule.email_regex, filter::regex),
+        ];
+        for (field, pattern, compile) in patterns {
+            if let Some(pattern) = pattern {
+                match compile(pattern) {
+                    Ok(pattern) => emails.push(pattern),
+                    Err(error) => problems.push(Problem::invalid(
+                        &format!("{}.{}", setting, field),
+                        pattern,
+                        &format!("is not a valid pattern: {}", error),
+                    )),
+                }
+            }
+        }
+        resolved.push(AliasRule {
+            id: rule.id.clone(),
+            emails,
+            domain: rule.domain.clone(),
+            description: rule.description.clone(),
+        });
+    }
+    resolved
+}
+
+impl LoggingConfig {
+    /// Validates the logging settings, with `RUST_LOG` taking precedence over the level.
+    fn resolve(&self, problems: &mut Vec<Problem>) -> LoggingSettings {
+        let mut settings = LoggingSettings {
+            privacy: self.privacy.unwrap_or(false),
+            ..Default::default()
+        };
+
+        let level = match std::env::var("RUST_LOG") {
+            Ok(level) => Some(("RUST_LOG", level)),
+            Err(_) => self.level.clone().map(|level| ("logging.level", level)),
+        };
+        if let Some((setting, level)) = level {
+            match level.parse::<LevelFilters>() {
+                Ok(levels) => settings.levels = levels,
+                Err(reason) => problems.push(Problem::invalid(setting, &level, &reason)),
+            }
+        }
+
+        match self.format.as_deref() {
+            None | Some("plain") => {}
+            Some("json") => settings.format = LogFormat::Json,
+            Some(format) => problems.push(Problem::invalid(
+                "logging.format",
+                format,
+                "must be one of \"plain\" or \"json\"",
+            )),
+        }
+
+        let mut file = self.file.clone().map(FileLog::new);
+        if let Some(max_size) = &self.max_size {
+            match logging::parse_size(max_size) {
+                Ok(max_size) => {
+                    if let Some(file) = &mut file {
+                        file.max_size = max_size;
+                    }
+                }
+                Err(reason) => {
+                    problems.push(Problem::invalid("logging.max_size", max_size, &reason))
+                }
+            }
+        }
+        match self.keep {
+            Some(0) => problems.push(Problem::invalid("logging.keep", "0", "must be at least 1")),
+            Some(keep) => {
+                if let Some(file) = &mut file {
+                    file.keep = keep;
+                }
+            }
+            None => {}
+        }
+        settings.file = file;
+
+        settings
+    }
+}
+
+/// The tokens and host the services are created with, see [`Config::read_credentials`].
+#[derive(Debug, Default)]
+pub struct Credentials {
+    pub hibp_token: Option<Secret>,
+    pub addy_token: Option<Secret>,
+    pub addy_host: Option<String>,
+}
+
+/// Reads a token from the environment variable, the file named by the variable with a `_FILE` suffix, or else the
+/// configured source, in that order. Returns `None` if it is in none of them.
+async fn read_token(
+    setting: &str,
+    name: &str,
+    var_os: fn(&str) -> Option<OsString>,
+    source: Option<&SecretSource>,
+) -> Result<Option<Secret>, Problem> {
+    if let Some(token) = var_os(name) {
+        return Ok(token.into_string().ok().map(Secret::new));
+    }
+    let file_variable = format!("{}_FILE", name);
+    let (setting, source) = match var_os(&file_variable) {
+        Some(file) => (file_variable, SecretSource::File { file: file.into() }),
+        None => match source {
+            Some(source) => (setting.to_string(), source.clone()),
+            None => return Ok(None),
+        },
+    };
+    source
+        .read()
+        .await
+        .map(Some)
+        .map_err(|error| Problem::Unavailable {
+            setting,
+            reason: error.message,
+        })
+}
+
+/// Something wrong with a single setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A required setting has not been given.
+    Missing {
+        setting: String,
+        /// The environment variable that can be used instead, if there is one.
+        variable: Option<String>,
+    },
+    /// A setting has been given a value that cannot be used.
+    Invalid {
+        setting: String,
+        value: String,
+        reason: String,
+    },
+    /// Settings have been given that cannot be used together.
+    Conflict { settings: Vec<String> },
+    /// The configuration file could not be read or parsed.
+    Unreadable { file: String, reason: String },
+    /// A secret could not be read from where the setting says it is kept.
+    Unavailable { setting: String, reason: String },
+}
+
+impl Problem {
+    pub fn missing(setting: &str, variable: &str) -> Self {
+        Problem::Missing {
+            setting: setting.to_string(),
+            variable: Some(variable.to_string()),
+        }
+    }
+
+    pub fn invalid(setting: &str, value: &str, reason: &str) -> Self {
+        Problem::Invalid {
+            setting: setting.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing {
+                setting,
+                variable: Some(variable),
+            } => write!(f, "{} (or {}) must be set", setting, variable),
+            Problem::Missing {
+                setting,
+                variable: None,
+            } => write!(f, "{} must be set", setting),
+            Problem::Invalid {
+                setting,
+                value,
+                reason,
+            } => write!(f, "{} \"{}\" {}", setting, value, reason),
+            Problem::Conflict { settings } => {
+                write!(f, "{} cannot be set together", settings.join(" and "))
+            }
+            Problem::Unreadable { file, reason } => {
+                write!(f, "unable to read {}: {}", file, reason)
+            }
+            Problem::Unavailable { setting, reason } => {
+                write!(f, "{} is unavailable: {}", setting, reason)
+            }
+        }
+    }
+}
+
+/// Every problem found with the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<Problem>,
+}
+
+impl ConfigError {
+    pub fn new(problems: Vec<Problem>) -> Self {
+        ConfigError { problems }
+    }
+
+    /// Combines the problems from each error, returning `None` if there were none.
+    pub fn collect<I>(errors: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Option<ConfigError>>,
+    {
+        let problems: Vec<Problem> = errors
+            .into_iter()
+            .flatten()
+            .flat_map(|error| error.problems)
+            .collect();
+        if problems.is_empty() {
+            None
+        } else {
+            Some(ConfigError::new(problems))
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The configuration is invalid:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl FromStr for Config {
+    type Err = toml::de::Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        toml::from_str(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::email_alias::DeletedAliases;
+    use crate::scan::UnknownAddressAction;
+    use crate::secret::Secret;
+
+    #[test]
+    fn load_returns_error_if_file_does_not_exist() {
+        let directory = tempdir().unwrap();
+
+        let config = Config::load(Some(&directory.path().join("missing.toml")));
+
+        assert!(matches!(
+            config.unwrap_err().problems.as_slice(),
+            [Problem::Unreadable { .. }]
+        ));
+    }
+
+    #[test]
+    fn load_reads_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[hibp]
+token = "hibp-token"
+
+[providers.addy]
+token = "anonaddy-token"
+host = "https://my-addy-instance.com"
+
+[policy]
+action = "report"
+
+[[notifiers]]
+type = "webhook"
+url = "https://example.com/hook"
+
+[output]
+format = "json"
+
+[schedule]
+interval = "12h"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(Some(&path)).unwrap();
+
+        assert_eq!(
+            config.hibp.token,
+            Some(SecretSource::Value(Secret::new("hibp-token")))
+        );
+        let anonaddy = config.providers.addy.unwrap();
+        assert_eq!(
+            anonaddy.token,
+            Some(SecretSource::Value(Secret::new("anonaddy-token")))
+        );
+        assert_eq!(
+            anonaddy.host.as_deref(),
+            Some("https://my-addy-instance.com")
+        );
+        assert_eq!(config.policy.action.as_deref(), Some("report"));
+        assert_eq!(config.notifiers.len(), 1);
+        assert_eq!(config.output.format.as_deref(), Some("json"));
+        assert_eq!(config.schedule.interval.as_deref(), Some("12h"));
+    }
+
+    #[test]
+    fn load_returns_error_with_line_of_invalid_toml() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[hibp]\ndomain_search = true\n\n[policy\naction = \"report\"\n",
+        )
+        .unwrap();
+
+        let error = Config::load(Some(&path)).unwrap_err();
+
+        match error.problems.as_slice() {
+            [Problem::Unreadable { reason, .. }] => {
+                assert!(reason.contains("line 4"), "{}", reason)
+            }
+            problems => panic!("Unexpected problems: {:?}", problems),
+        }
+    }
+
+    #[test]
+    fn from_str_returns_error_for_unknown_field() {
+        let config = Config::from_str("[hibp]\ntokn = \"typo\"");
+
+        assert!(config.is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_credentials_prefers_environment_variables() {
+        std::env::set_var("HIBP_TOKEN", "env-token");
+        std::env::remove_var("HIBP_TOKEN_FILE");
+        std::env::remove_var("ADDY_TOKEN");
+        std::env::remove_var("ADDY_TOKEN_FILE");
+        std::env::remove_var("ADDY_HOST");
+        let config = Config::from_str(
+            "[hibp]\ntoken = \"file-token\"\n[providers.addy]\ntoken = \"anonaddy-token\"",
+        )
+        .unwrap();
+
+        let (credentials, error) = config.read_credentials().await;
+        std::env::remove_var("HIBP_TOKEN");
+
+        assert!(error.is_none());
+        assert_eq!(credentials.hibp_token.unwrap().expose(), "env-token");
+        assert_eq!(credentials.addy_token.unwrap().expose(), "anonaddy-token");
+        assert!(credentials.addy_host.is_none());
+        assert!(std::env::var("ADDY_TOKEN").is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_credentials_accepts_names_from_before_rebrand() {
+        std::env::remove_var("HIBP_TOKEN");
+        std::env::remove_var("HIBP_TOKEN_FILE");
+        std::env::remove_var("ADDY_TOKEN");
+        std::env::remove_var("ADDY_TOKEN_FILE");
+        std::env::remove_var("ADDY_HOST");
+        std::env::set_var("ANONADDY_TOKEN", "legacy-token");
+        let config = Config::from_str(
+            "[providers.anonaddy]\ntoken = \"anonaddy-token\"\nhost = \"https://app.anonaddy.com\"",
+        )
+        .unwrap();
+
+        let (credentials, _) = config.read_credentials().await;
+        std::env::remove_var("ANONADDY_TOKEN");
+
+        assert!(credentials.hibp_token.is_none());
+        assert_eq!(credentials.addy_token.unwrap().expose(), "legacy-token");
+        assert_eq!(credentials.addy_host.unwrap(), "https://app.anonaddy.com");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_credentials_reads_tokens_from_sources() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("hibp_token");
+        std::fs::write(&path, "secret-file-token\n").unwrap();
+        std::env::remove_var("HIBP_TOKEN");
+        std::env::set_var("HIBP_TOKEN_FILE", &path);
+        std::env::remove_var("ADDY_TOKEN");
+        std::env::remove_var("ADDY_TOKEN_FILE");
+        let config = Config::from_str(
+            r#"
+hibp.token = "ignored-for-file-variable"
+providers.addy.token = { command = "echo command-token" }
+"#,
+        )
+        .unwrap();
+
+        let (credentials, error) = config.read_credentials().await;
+        std::env::remove_var("HIBP_TOKEN_FILE");
+
+        assert!(error.is_none());
+        assert_eq!(
+            credentials.hibp_token.unwrap().expose(),
+            "secret-file-token"
+        );
+        assert_eq!(credentials.addy_token.unwrap().expose(), "command-token");
+        assert!(std::env::var("HIBP_TOKEN").is_err());
+        assert!(std::env::var("ADDY_TOKEN").is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_credentials_returns_every_unavailable_token() {
+        let directory = tempdir().unwrap();
+        std::env::remove_var("HIBP_TOKEN");
+        std::env::remove_var("HIBP_TOKEN_FILE");
+        std::env::remove_var("ADDY_TOKEN");
+        std::env::remove_var("ADDY_TOKEN_FILE");
+        let config = Config::from_str(&format!(
+            r#"
+hibp.token = {{ file = "{}" }}
+providers.addy.token = {{ command = "exit 1" }}
+"#,
+            directory.path().join("missing").display()
+        ))
+        .unwrap();
+
+        let (credentials, error) = config.read_credentials().await;
+
+        assert!(credentials.hibp_token.is_none());
+        assert!(credentials.addy_token.is_none());
+        assert!(matches!(
+            error.unwrap().problems.as_slice(),
+            [
+                Problem::Unavailable { setting: hibp, .. },
+                Problem::Unavailable { setting: anonaddy, .. },
+            ] if hibp == "hibp.token" && anonaddy == "providers.addy.token"
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_returns_defaults_for_empty_config() {
+        let settings = Config::default().resolve().unwrap();
+
+        assert_eq!(settings.policy, Policy::default());
+        assert_eq!(settings.output_format, OutputFormat::Text);
+        assert!(settings.notifiers.is_empty());
+        assert!(settings.schedule.is_none());
+        assert_eq!(settings.retry, RetryPolicy::default());
+        assert_eq!(settings.logging, LoggingSettings::default());
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_returns_settings_for_valid_config() {
+        let config = Config::from_str(
+            r#"
+policy.action = "report"
+policy.unknown_addresses = "disable_catch_all"
+output.format = "json"
+schedule.cron = "0 0 3 * * *"
+retry.attempts = 5
+retry.delay = "500ms"
+logging.privacy = true
+logging.level = "warn"
+logging.format = "json"
+logging.file = "app.log"
+logging.max_size = "1MB"
+logging.keep = 2
+dashboard.hosts = ["aliases.example.com"]
+
+[[policy.overrides]]
+alias = "shopping@example.com"
+action = "replace"
+
+[[notifiers]]
+type = "webhook"
+url = "https://example.com/hook"
+"#,
+        )
+        .unwrap();
+
+        let settings = config.resolve().unwrap();
+
+        assert_eq!(settings.policy.action, BreachAction::Report);
+        assert_eq!(
+            settings.policy.unknown_addresses,
+            UnknownAddressAction::DisableCatchAll
+        );
+        assert_eq!(
+            settings.policy.overrides,
+            vec![ActionOverride {
+                alias: "shopping@example.com".to_string(),
+                action: BreachAction::Replace,
+            }]
+        );
+        assert_eq!(settings.output_format, OutputFormat::Json);
+        assert_eq!(
+            settings.notifiers,
+            vec![Notifier::Webhook {
+                url: "https://example.com/hook".parse().unwrap()
+            }]
+        );
+        assert!(matches!(settings.schedule, Some(Schedule::Cron(_))));
+        assert_eq!(
+            settings.retry,
+            RetryPolicy {
+                attempts: 5,
+                delay: std::time::Duration::from_millis(500)
+            }
+        );
+        assert_eq!(
+            settings.logging,
+            LoggingSettings {
+                levels: LevelFilters {
+                    root: log::LevelFilter::Warn,
+                    modules: vec![]
+                },
+                format: LogFormat::Json,
+                file: Some(FileLog {
+                    path: PathBuf::from("app.log"),
+                    max_size: 1024 * 1024,
+                    keep: 2
+                }),
+                privacy: true,
+            }
+        );
+        assert_eq!(settings.dashboard_hosts, vec!["aliases.example.com"]);
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_prefers_rust_log_over_level() {
+        std::env::set_var("RUST_LOG", "debug,hyper=off");
+        let config = Config::from_str("logging.level = \"warn\"").unwrap();
+
+        let settings = config.resolve();
+        std::env::remove_var("RUST_LOG");
+
+        let levels = settings.unwrap().logging.levels;
+        assert_eq!(levels.root, log::LevelFilter::Debug);
+        assert_eq!(
+            levels.modules,
+            vec![("hyper".to_string(), log::LevelFilter::Off)]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_compiles_alias_rules() {
+        let config = Config::from_str(
+            r#"
+[aliases]
+search = "shop"
+domains = ["johndoe.anonaddy.com"]
+deleted = "only"
+check_inactive = true
+
+[[aliases.exclude]]
+email = "team-*@example.com"
+
+[[aliases.exclude]]
+domain = "example.org"
+description = "shared"
+"#,
+        )
+        .unwrap();
+
+        let settings = config.resolve().unwrap();
+        let filter = settings.filter;
+
+        assert_eq!(settings.query.search.as_deref(), Some("shop"));
+        assert_eq!(settings.query.domains, vec!["johndoe.anonaddy.com"]);
+        assert_eq!(settings.query.deleted, DeletedAliases::Only);
+        assert!(settings.check_inactive);
+        assert!(!settings.domain_search);
+        assert!(filter.include.is_empty());
+        assert_eq!(filter.exclude.len(), 2);
+        assert!(filter.exclude[0].emails[0].is_match("team-support@example.com"));
+        assert_eq!(filter.exclude[1].domain.as_deref(), Some("example.org"));
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_returns_problems_for_alias_rules() {
+        let config = Config::from_str(
+            r#"
+[[aliases.include]]
+
+[[aliases.exclude]]
+email_regex = "("
+"#,
+        )
+        .unwrap();
+
+        let error = config.resolve().unwrap_err();
+
+        assert_eq!(error.problems.len(), 2);
+        assert_eq!(
+            error.problems[0],
+            Problem::invalid(
+                "aliases.include[0]",
+                "{}",
+                "must set at least one of id, email, email_regex, domain or description"
+            )
+        );
+        assert!(error.problems[1]
+            .to_string()
+            .starts_with("aliases.exclude[0].email_regex \"(\" is not a valid pattern"));
+    }
+
+    #[test]
+    #[serial]
+    fn resolve_returns_every_problem() {
+        let config = Config::from_str(
+            r#"
+policy.action = "explode"
+output.format = "xml"
+schedule.interval = "12h"
+schedule.cron = "0 0 3 * * *"
+retry.attempts = 0
+retry.delay = "soon"
+logging.level = "loud"
+logging.format = "xml"
+
+[[notifiers]]
+type = "webhook"
+
+[[notifiers]]
+type = "carrier-pigeon"
+"#,
+        )
+        .unwrap();
+
+        let error = config.resolve().unwrap_err();
+
+        assert_eq!(
+            error.problems,
+            vec![
+                Problem::invalid(
+                    "policy.action",
+                    "explode",
+                    "must be one of \"deactivate\", \"delete\", \"tag\", \"replace\" or \"report\""
+                ),
+                Problem::Missing {
+                    setting: "notifiers[0].url".to_string(),
+                    variable: None
+                },
+                Problem::invalid("notifiers[1].type", "carrier-pigeon", "must be \"webhook\""),
+                Problem::invalid(
+                    "output.format",
+                    "xml",
+                    "must be one of \"text\" or \"json\""
+                ),
+                Problem::Conflict {
+                    settings: vec!["schedule.interval".to_string(), "schedule.cron".to_string()]
+                },
+                Problem::invalid("retry.attempts", "0", "must be at least 1"),
+                Problem::invalid(
+                    "retry.delay",
+                    "soon",
+                    "is not a valid duration: expected number at 0"
+                ),
+                Problem::invalid(
+                    "logging.level",
+                    "loud",
+                    "has unknown level \"loud\", expected one of off, error, warn, info, debug or trace"
+                ),
+                Problem::invalid(
+                    "logging.format",
+                    "xml",
+                    "must be one of \"plain\" or \"json\""
+                ),
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "The configuration is invalid:
+  - policy.action \"explode\" must be one of \"deactivate\", \"delete\", \"tag\", \"replace\" or \"report\"
+  - notifiers[0].url must be set
+  - notifiers[1].type \"carrier-pigeon\" must be \"webhook\"
+  - output.format \"xml\" must be one of \"text\" or \"json\"
+  - schedule.interval and schedule.cron cannot be set together
+  - retry.attempts \"0\" must be at least 1
+  - retry.delay \"soon\" is not a valid duration: expected number at 0
+  - logging.level \"loud\" has unknown level \"loud\", expected one of off, error, warn, info, debug or trace
+  - logging.format \"xml\" must be one of \"plain\" or \"json\""
+        );
+    }
+
+    #[test]
+    fn collect_combines_problems() {
+        let error = ConfigError::collect([
+            Some(ConfigError::new(vec![Problem::missing(
+                "hibp.token",
+                "HIBP_TOKEN",
+            )])),
+            None,
+            Some(ConfigError::new(vec![Problem::missing(
+                "providers.addy.token",
+                "ADDY_TOKEN",
+            )])),
+        ]);
+
+        assert_eq!(
+            error.unwrap().problems,
+            vec![
+                Problem::missing("hibp.token", "HIBP_TOKEN"),
+                Problem::missing("providers.addy.token", "ADDY_TOKEN"),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_returns_none_without_problems() {
+        assert!(ConfigError::collect([None, None]).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info, warn};
+
+use crate::error::ApiError;
+use crate::scan::Scanner;
+use crate::shutdown::Shutdown;
+
+/// When the daemon should run each scan.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Scan straight away, then again each time the duration elapses.
+    Interval(Duration),
+    /// Scan at each time matched by the cron expression (in UTC).
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Parses a cron expression in the `sec min hour day-of-month month day-of-week [year]` format.
+    pub fn cron(expression: &str) -> Result<Self, cron::error::Error> {
+        let schedule = cron::Schedule::from_str(expression)?;
+        Ok(Schedule::Cron(Box::new(schedule)))
+    }
+
+    fn initial_delay(&self) -> Duration {
+        match self {
+            Schedule::Interval(_) => Duration::ZERO,
+            Schedule::Cron(_) => self.next_delay(),
+        }
+    }
+
+    fn next_delay(&self) -> Duration {
+        match self {
+            Schedule::Interval(interval) => *interval,
+            Schedule::Cron(schedule) => schedule
+                .upcoming(Utc)
+                .next()
+                .and_then(|next| (next - Utc::now()).to_std().ok())
+                .unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
+/// Repeatedly scans the aliases on the given schedule until shutdown is requested.
+///
+/// A failed scan is logged rather than stopping the daemon, so the next scheduled scan still runs.
+pub async fn run(scanner: &Scanner<'_>, schedule: &Schedule, mut shutdown: Shutdown) {
+    info!("Starting daemon.");
+    let mut delay = schedule.initial_delay();
+    loop {
+        if !delay.is_zero() {
+            info!("Next scan in {}.", humantime::format_duration(delay));
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.wait() => break,
+        }
+        if let Err(error) = scanner.scan_and_report(&shutdown).await {
+            match error.downcast_ref::<ApiError>() {
+                Some(error) if error.is_transient() => {
+                    warn!("Scan failed, will try again at the next scan: {}", error)
+                }
+                _ => error!("Scan failed: {}", error),
+            }
+        }
+        if shutdown.is_requested() {
+            break;
+        }
+        delay = schedule.next_delay();
+    }
+    info!("Daemon stopped.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_returns_error_for_invalid_expression() {
+        let schedule = Schedule::cron("not a cron expression");
+
+        assert!(schedule.is_err());
+    }
+
+    #[test]
+    fn cron_returns_schedule_for_valid_expression() {
+        let schedule = Schedule::cron("0 0 3 * * *");
+
+        assert!(matches!(schedule, Ok(Schedule::Cron(_))));
+    }
+
+    #[test]
+    fn initial_delay_is_zero_for_interval() {
+        let schedule = Schedule::Interval(Duration::from_secs(60));
+
+        assert_eq!(schedule.initial_delay(), Duration::ZERO);
+        assert_eq!(schedule.next_delay(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn next_delay_for_cron_is_within_a_second() {
+        let schedule = Schedule::cron("* * * * * *").unwrap();
+
+        assert!(schedule.initial_delay() <= Duration::from_secs(1));
+        assert!(schedule.next_delay() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,641 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{FromRequest, Path, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Form, Router};
+use chrono::Utc;
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::email_alias::{AliasQuery, AliasService};
+use crate::error::ApiError;
+use crate::hibp::HIBP;
+use crate::scan::RetryPolicy;
+use crate::secret::Secret;
+use crate::state::{AliasState, StateStore};
+
+/// Everything the dashboard needs to list and act on aliases.
+#[derive(Clone)]
+pub struct Dashboard {
+    pub alias_service: Arc<dyn AliasService>,
+    pub hibp: Arc<HIBP<'static>>,
+    pub state: Arc<StateStore>,
+    /// How re-checking an alias is retried, such as when haveibeenpwned.com is rate limiting.
+    pub retry: RetryPolicy,
+    /// Sent with every form on the dashboard and required by the actions, so that other sites cannot trigger them.
+    pub form_token: Secret,
+    /// The values of the `Host` header the dashboard answers to, so that a page on another site cannot reach it by
+    /// pointing its own domain at the listen address (DNS rebinding). See [`allowed_hosts`].
+    pub hosts: Vec<String>,
+}
+
+/// Generates a random token for the forms on the dashboard, see [`Dashboard::form_token`].
+pub fn generate_form_token() -> Result<Secret, getrandom::Error> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes)?;
+    Ok(Secret::new(
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>(),
+    ))
+}
+
+/// The hosts the dashboard answers to when listening on the address: the address itself, `localhost` for a loopback
+/// address, and any configured for a reverse proxy.
+pub fn allowed_hosts(listen: SocketAddr, configured: &[String]) -> Vec<String> {
+    let mut hosts = vec![listen.to_string()];
+    if listen.ip().is_loopback() {
+        hosts.push(format!("localhost:{}", listen.port()));
+    }
+    if listen.port() == 80 {
+        // Browsers leave out the default port.
+        hosts = hosts
+            .into_iter()
+            .flat_map(|host| [host.trim_end_matches(":80").to_string(), host])
+            .collect();
+    }
+    hosts.extend(configured.iter().cloned());
+    hosts
+}
+
+pub fn router(dashboard: Dashboard) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/aliases/{id}/check", post(check))
+        .route("/aliases/{id}/deactivate", post(deactivate))
+        .route("/aliases/{id}/reactivate", post(reactivate))
+        .route("/aliases/{id}/acknowledge", post(acknowledge))
+        .route_layer(middleware::from_fn_with_state(
+            dashboard.clone(),
+            check_host,
+        ))
+        .with_state(dashboard)
+}
+
+/// Turns away requests for any host the dashboard does not answer to, before the page or its form token is served.
+async fn check_host(
+    State(dashboard): State<Dashboard>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DashboardError> {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    let allowed = host.is_some_and(|host| {
+        dashboard
+            .hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    });
+    if !allowed {
+        warn!(
+            "Rejected a dashboard request for the host {}.",
+            host.unwrap_or("(none)")
+        );
+        return Err(DashboardError {
+            status: StatusCode::MISDIRECTED_REQUEST,
+            message: "The dashboard is not served for this host.".to_string(),
+        });
+    }
+    Ok(next.run(request).await)
+}
+
+struct DashboardError {
+    status: StatusCode,
+    message: String,
+}
+
+impl DashboardError {
+    /// Passes on a not found error from the service, treating anything else as the service failing.
+    fn from_api(error: ApiError) -> Self {
+        let status = match error.status() {
+            Some(404) => StatusCode::NOT_FOUND,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        DashboardError {
+            status,
+            message: error.to_string(),
+        }
+    }
+
+    fn internal(error: Box<dyn std::error::Error>) -> Self {
+        DashboardError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Proof that an action was sent from a form on the dashboard itself.
+///
+/// The form has to carry the dashboard's token, and a browser sending it from another site is turned away on the
+/// `Origin` or `Referer` header alone.
+struct FromDashboard;
+
+#[derive(Deserialize)]
+struct ActionForm {
+    token: String,
+}
+
+impl FromRequest<Dashboard> for FromDashboard {
+    type Rejection = DashboardError;
+
+    async fn from_request(
+        request: Request,
+        dashboard: &Dashboard,
+    ) -> Result<Self, Self::Rejection> {
+        let forbidden = |reason: &str| {
+            warn!("Rejected a dashboard action as {}.", reason);
+            DashboardError {
+                status: StatusCode::FORBIDDEN,
+                message: "This action can only be taken from the dashboard.".to_string(),
+            }
+        };
+        if !is_same_origin(request.headers()) {
+            return Err(forbidden("it came from another site"));
+        }
+        let Form(form) = Form::<ActionForm>::from_request(request, dashboard)
+            .await
+            .map_err(|_| forbidden("it did not include the form token"))?;
+        if form.token != dashboard.form_token.expose() {
+            return Err(forbidden("its form token did not match"));
+        }
+        Ok(FromDashboard)
+    }
+}
+
+/// Whether the `Origin` header, or failing that the `Referer` header, names the host the request was sent to. Requests
+/// with neither, such as those from scripts, are let through to be checked on the form token alone.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(host) = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+    else {
+        return false;
+    };
+    let source = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .map(|source| source.to_str().unwrap_or_default());
+    source.is_none_or(|source| {
+        ["http://", "https://"].iter().any(|scheme| {
+            source
+                .strip_prefix(scheme)
+                .and_then(|rest| rest.strip_prefix(host))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    })
+}
+
+impl IntoResponse for DashboardError {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+async fn index(State(dashboard): State<Dashboard>) -> Result<Html<String>, DashboardError> {
+    let aliases = dashboard
+        .alias_service
+        .get_aliases(&AliasQuery::default())
+        .await
+        .map_err(DashboardError::from_api)?;
+    let state = dashboard.state.snapshot();
+
+    let mut rows = String::new();
+    for alias in aliases {
+        let alias_state = state.aliases.get(alias.get_id());
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(alias.get_email()),
+            escape(alias.get_description().unwrap_or("")),
+            if alias.is_active() { "Yes" } else { "No" },
+            alias_state
+                .and_then(|alias_state| alias_state.last_checked)
+                .map(|last_checked| last_checked.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "Never".to_string()),
+            breach_status(alias_state),
+            actions(
+                alias.get_id(),
+                alias.is_active(),
+                alias_state,
+                dashboard.form_token.expose()
+            ),
+        ));
+    }
+
+    Ok(Html(format!(
+        "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>Has my alias been pwned?</title></head>
+<body>
+<h1>Has my alias been pwned?</h1>
+<table>
+<thead><tr><th>Email</th><th>Description</th><th>Active</th><th>Last checked</th><th>Breaches</th><th></th></tr></thead>
+<tbody>
+{}
+</tbody>
+</table>
+</body>
+</html>",
+        rows
+    )))
+}
+
+fn breach_status(alias_state: Option<&AliasState>) -> String {
+    match alias_state {
+        Some(alias_state) if alias_state.last_checked.is_some() => {
+            if alias_state.breaches.is_empty() {
+                return "None".to_string();
+            }
+            alias_state
+                .breaches
+                .iter()
+                .map(|breach| {
+                    if alias_state.acknowledged.contains(breach) {
+                        format!("{} (acknowledged)", escape(breach))
+                    } else {
+                        format!("<strong>{}</strong>", escape(breach))
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+        _ => "Not checked".to_string(),
+    }
+}
+
+fn actions(id: &str, active: bool, alias_state: Option<&AliasState>, token: &str) -> String {
+    let form = |action: &str, label: &str| {
+        format!(
+            "<form method=\"post\" action=\"/aliases/{}/{}\"><input type=\"hidden\" name=\"token\" value=\"{}\"><button>{}</button></form>",
+            escape(id),
+            action,
+            escape(token),
+            label
+        )
+    };
+    let mut actions = form("check", "Re-check");
+    if active {
+        actions.push_str(&form("deactivate", "Deactivate"));
+    } else {
+        actions.push_str(&form("reactivate", "Reactivate"));
+    }
+    if alias_state.is_some_and(|alias_state| !alias_state.unacknowledged_breaches().is_empty()) {
+        actions.push_str(&form("acknowledge", "Acknowledge"));
+    }
+    actions
+}
+
+async fn check(
+    State(dashboard): State<Dashboard>,
+    Path(id): Path<String>,
+    _: FromDashboard,
+) -> Result<Redirect, DashboardError> {
+    let alias = dashboard
+        .alias_service
+        .get_alias(&id)
+        .await
+        .map_err(DashboardError::from_api)?;
+    info!("Re-checking breaches for {}.", alias);
+    let breaches = dashboard
+        .retry
+        .run_hibp(|| dashboard.hibp.get_breaches(alias.get_email()))
+        .await
+        .map_err(DashboardError::from_api)?;
+    dashboard
+        .state
+        .record_check(
+            alias.as_ref(),
+            breaches
+                .iter()
+                .map(|breach| breach.name().to_string())
+                .collect(),
+        )
+        .map_err(DashboardError::internal)?;
+    Ok(Redirect::to("/"))
+}
+
+async fn deactivate(
+    State(dashboard): State<Dashboard>,
+    Path(id): Path<String>,
+    _: FromDashboard,
+) -> Result<Redirect, DashboardError> {
+    dashboard
+        .alias_service
+        .deactivate_alias(&id)
+        .await
+        .map_err(DashboardError::from_api)?;
+    dashboard
+        .state
+        .record_deactivation(&id, Utc::now())
+        .map_err(DashboardError::internal)?;
+    Ok(Redirect::to("/"))
+}
+
+async fn reactivate(
+    State(dashboard): State<Dashboard>,
+    Path(id): Path<String>,
+    _: FromDashboard,
+) -> Result<Redirect, DashboardError> {
+    dashboard
+        .alias_service
+        .reactivate_alias(&id)
+        .await
+        .map_err(DashboardError::from_api)?;
+    dashboard
+        .state
+        .record_reactivation(&id, Utc::now())
+        .map_err(DashboardError::internal)?;
+    Ok(Redirect::to("/"))
+}
+
+async fn acknowledge(
+    State(dashboard): State<Dashboard>,
+    Path(id): Path<String>,
+    _: FromDashboard,
+) -> Result<Redirect, DashboardError> {
+    info!("Acknowledging breaches for alias {}.", id);
+    dashboard
+        .state
+        .acknowledge_all(&id)
+        .map_err(DashboardError::internal)?;
+    Ok(Redirect::to("/"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serial_test::serial;
+    use tempfile::{tempdir, TempDir};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::email_alias::fakes::{FakeAlias, FakeAliasService};
+    use crate::secret::Secret;
+
+    /// Creates a dashboard over the given aliases, with its state kept in a temporary directory.
+    pub fn test_dashboard(aliases: Vec<FakeAlias>) -> (Dashboard, Arc<FakeAliasService>, TempDir) {
+        let client: &'static reqwest::Client = Box::leak(Box::new(reqwest::Client::new()));
+        let directory = tempdir().unwrap();
+        let alias_service = Arc::new(FakeAliasService::new(aliases));
+        let dashboard = Dashboard {
+            alias_service: alias_service.clone(),
+            hibp: Arc::new(HIBP::new(client, Some(Secret::new("test-token"))).unwrap()),
+            state: Arc::new(StateStore::load(&directory.path().join("state.json")).unwrap()),
+            retry: RetryPolicy::default(),
+            form_token: Secret::new("form-token"),
+            hosts: vec![],
+        };
+        (dashboard, alias_service, directory)
+    }
+
+    /// Sends an action as the dashboard's own form would.
+    async fn post(url: &str, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(url)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("token={}", token))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    async fn start(mut dashboard: Dashboard) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        dashboard.hosts = allowed_hosts(address, &["aliases.example.com".to_string()]);
+        tokio::spawn(async move { axum::serve(listener, router(dashboard)).await });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn index_lists_aliases_with_breach_status() {
+        let checked = FakeAlias::new("checked-id", "checked@email.com");
+        let unchecked = FakeAlias {
+            description: Some("<shared>".to_string()),
+            ..FakeAlias::new("unchecked-id", "unchecked@email.com")
+        };
+        let (dashboard, _, _directory) = test_dashboard(vec![checked.clone(), unchecked]);
+        dashboard
+            .state
+            .record_check(&checked, vec!["Adobe".to_string()])
+            .unwrap();
+        let url = start(dashboard).await;
+
+        let body = reqwest::get(&url).await.unwrap().text().await.unwrap();
+
+        assert!(body.contains("checked@email.com"));
+        assert!(body.contains("<strong>Adobe</strong>"));
+        assert!(body.contains("/aliases/checked-id/acknowledge"));
+        assert!(body.contains("unchecked@email.com"));
+        assert!(body.contains("&lt;shared&gt;"));
+        assert!(body.contains("Not checked"));
+        assert!(!body.contains("/aliases/unchecked-id/acknowledge"));
+        assert!(body.contains("name=\"token\" value=\"form-token\""));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn deactivate_deactivates_alias() {
+        let alias = FakeAlias::new("test-id", "email@email.com");
+        let (dashboard, alias_service, _directory) = test_dashboard(vec![alias.clone()]);
+        dashboard.state.record_check(&alias, vec![]).unwrap();
+        let state = dashboard.state.clone();
+        let url = start(dashboard).await;
+
+        let response = post(&format!("{}/aliases/test-id/deactivate", url), "form-token").await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            *alias_service.deactivated.lock().unwrap(),
+            vec!["test-id".to_string()]
+        );
+        assert!(!state.snapshot().aliases.get("test-id").unwrap().active);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reactivate_reactivates_alias() {
+        let alias = FakeAlias {
+            active: false,
+            ..FakeAlias::new("test-id", "email@email.com")
+        };
+        let (dashboard, alias_service, _directory) = test_dashboard(vec![alias.clone()]);
+        dashboard.state.record_check(&alias, vec![]).unwrap();
+        let state = dashboard.state.clone();
+        let url = start(dashboard).await;
+
+        let response = post(&format!("{}/aliases/test-id/reactivate", url), "form-token").await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            *alias_service.reactivated.lock().unwrap(),
+            vec!["test-id".to_string()]
+        );
+        assert!(state.snapshot().aliases.get("test-id").unwrap().active);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn acknowledge_acknowledges_breaches() {
+        let alias = FakeAlias::new("test-id", "email@email.com");
+        let (dashboard, _, _directory) = test_dashboard(vec![alias.clone()]);
+        dashboard
+            .state
+            .record_check(&alias, vec!["Adobe".to_string()])
+            .unwrap();
+        let state = dashboard.state.clone();
+        let url = start(dashboard).await;
+
+        let response = post(
+            &format!("{}/aliases/test-id/acknowledge", url),
+            "form-token",
+        )
+        .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            state
+                .snapshot()
+                .aliases
+                .get("test-id")
+                .unwrap()
+                .acknowledged,
+            vec!["Adobe".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn check_returns_not_found_for_unknown_alias() {
+        let (dashboard, _, _directory) = test_dashboard(vec![]);
+        let url = start(dashboard).await;
+
+        let response = post(&format!("{}/aliases/unknown-id/check", url), "form-token").await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn actions_are_rejected_without_form_token() {
+        let alias = FakeAlias::new("test-id", "email@email.com");
+        let (dashboard, alias_service, _directory) = test_dashboard(vec![alias]);
+        let url = start(dashboard).await;
+
+        let missing = reqwest::Client::new()
+            .post(format!("{}/aliases/test-id/deactivate", url))
+            .send()
+            .await
+            .unwrap();
+        let wrong = post(&format!("{}/aliases/test-id/deactivate", url), "guessed").await;
+
+        assert_eq!(missing.status(), 403);
+        assert_eq!(wrong.status(), 403);
+        assert!(alias_service.deactivated.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn actions_are_rejected_from_another_site() {
+        let alias = FakeAlias::new("test-id", "email@email.com");
+        let (dashboard, alias_service, _directory) = test_dashboard(vec![alias]);
+        let url = start(dashboard).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/aliases/test-id/deactivate", url))
+            .header("Origin", "https://example.com")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("token=form-token")
+            .send()
+            .await
+            .unwrap();
+        let same_site = reqwest::Client::new()
+            .post(format!("{}/aliases/test-id/deactivate", url))
+            .header("Origin", url.as_str())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("token=form-token")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 403);
+        assert_eq!(same_site.status(), 200);
+        assert_eq!(
+            *alias_service.deactivated.lock().unwrap(),
+            vec!["test-id".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn requests_for_other_hosts_are_rejected() {
+        let alias = FakeAlias::new("test-id", "email@email.com");
+        let (dashboard, alias_service, _directory) = test_dashboard(vec![alias]);
+        let url = start(dashboard).await;
+        let port = url.rsplit(':').next().unwrap();
+
+        let rebound = reqwest::Client::new()
+            .get(&url)
+            .header("Host", format!("attacker.example.com:{}", port))
+            .send()
+            .await
+            .unwrap();
+        let action = reqwest::Client::new()
+            .post(format!("{}/aliases/test-id/deactivate", url))
+            .header("Host", format!("attacker.example.com:{}", port))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("token=form-token")
+            .send()
+            .await
+            .unwrap();
+        let localhost = reqwest::Client::new()
+            .get(&url)
+            .header("Host", format!("localhost:{}", port))
+            .send()
+            .await
+            .unwrap();
+        let configured = reqwest::Client::new()
+            .get(&url)
+            .header("Host", "aliases.example.com")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(rebound.status(), 421);
+        assert!(!rebound.text().await.unwrap().contains("form-token"));
+        assert_eq!(action.status(), 421);
+        assert!(alias_service.deactivated.lock().unwrap().is_empty());
+        assert_eq!(localhost.status(), 200);
+        assert_eq!(configured.status(), 200);
+    }
+
+    #[test]
+    fn allowed_hosts_include_localhost_and_default_port() {
+        assert_eq!(
+            allowed_hosts("127.0.0.1:9090".parse().unwrap(), &[]),
+            vec!["127.0.0.1:9090", "localhost:9090"]
+        );
+        assert_eq!(
+            allowed_hosts(
+                "192.168.1.2:80".parse().unwrap(),
+                &["aliases.example.com".to_string()]
+            ),
+            vec!["192.168.1.2", "192.168.1.2:80", "aliases.example.com"]
+        );
+    }
+}
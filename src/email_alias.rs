@@ -1,39 +1,553 @@
 use std::fmt::{Debug, Display};
 
 use async_trait::async_trait;
-pub trait Alias: Debug {
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::redact;
+
+pub trait Alias: Debug + Send + Sync {
     fn is_active(&self) -> bool;
+    /// Whether the alias has been deleted, but can still be restored.
+    fn is_deleted(&self) -> bool;
     fn get_id(&self) -> &str;
     fn get_email(&self) -> &str;
     fn get_description(&self) -> Option<&str>;
+    /// The name of the service providing the alias, e.g. `addy.io`.
+    fn get_provider(&self) -> &str;
+    /// The email addresses the alias forwards to, or none if it forwards to the account's default recipient.
+    fn get_recipients(&self) -> Vec<&str>;
+    /// When the alias was created, if the service says.
+    fn get_created_at(&self) -> Option<DateTime<Utc>>;
+    /// When the alias was last changed, if the service says.
+    fn get_updated_at(&self) -> Option<DateTime<Utc>>;
+    fn get_counters(&self) -> AliasCounters;
+
+    fn get_domain(&self) -> &str {
+        self.get_email()
+            .rsplit_once('@')
+            .map_or("", |(_, domain)| domain)
+    }
+
+    /// Copies everything known about the alias, so it can be kept without the service's own type.
+    fn info(&self) -> AliasInfo {
+        AliasInfo {
+            provider: self.get_provider().to_string(),
+            id: self.get_id().to_string(),
+            email: self.get_email().to_string(),
+            domain: self.get_domain().to_string(),
+            description: self.get_description().map(str::to_string),
+            active: self.is_active(),
+            deleted: self.is_deleted(),
+            recipients: self
+                .get_recipients()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            created_at: self.get_created_at(),
+            updated_at: self.get_updated_at(),
+            counters: self.get_counters(),
+        }
+    }
 }
 
-#[async_trait]
-pub trait AliasService {
-    async fn get_aliases(&self) -> Result<Vec<Box<dyn Alias>>, Box<dyn std::error::Error>>;
+/// How many emails an alias has handled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AliasCounters {
+    pub forwarded: u64,
+    pub blocked: u64,
+    pub replied: u64,
+    pub sent: u64,
+}
 
-    async fn deactivate_alias(&self, id: &str) -> Result<(), Box<dyn std::error::Error>>;
+/// An alias as described by any service, which can be exported and read back in without the service.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AliasInfo {
+    pub provider: String,
+    pub id: String,
+    pub email: String,
+    pub domain: String,
+    pub description: Option<String>,
+    pub active: bool,
+    pub deleted: bool,
+    pub recipients: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub counters: AliasCounters,
 }
 
-#[derive(Debug, Clone)]
-pub struct AliasError {
-    pub message: String,
+/// Masks the addresses in privacy mode, as aliases are written to the debug log.
+impl Debug for AliasInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let recipients: Vec<_> = self
+            .recipients
+            .iter()
+            .map(|recipient| redact::Email(recipient))
+            .collect();
+        f.debug_struct("AliasInfo")
+            .field("provider", &self.provider)
+            .field("id", &self.id)
+            .field("email", &redact::Email(&self.email))
+            .field("domain", &self.domain)
+            .field("description", &self.description)
+            .field("active", &self.active)
+            .field("deleted", &self.deleted)
+            .field("recipients", &recipients)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .field("counters", &self.counters)
+            .finish()
+    }
 }
 
-impl AliasError {
-    pub fn new(message: String) -> Self {
-        AliasError { message }
+impl Alias for AliasInfo {
+    fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_email(&self) -> &str {
+        &self.email
+    }
+
+    fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn get_provider(&self) -> &str {
+        &self.provider
+    }
+
+    fn get_recipients(&self) -> Vec<&str> {
+        self.recipients.iter().map(String::as_str).collect()
+    }
+
+    fn get_created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    fn get_updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    fn get_counters(&self) -> AliasCounters {
+        self.counters
+    }
+
+    fn get_domain(&self) -> &str {
+        &self.domain
+    }
+
+    fn info(&self) -> AliasInfo {
+        self.clone()
     }
 }
 
-impl Display for AliasError {
+/// Describes the alias for log output, masking the email address in privacy mode.
+impl Display for dyn Alias + '_ {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(
+            f,
+            "{} - {}",
+            redact::Email(self.get_email()),
+            self.get_description().unwrap_or("")
+        )
     }
 }
 
-impl std::error::Error for AliasError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+/// The account the aliases belong to, along with how many aliases and usernames it can have.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AccountSummary {
+    pub provider: String,
+    /// The version of the service, if it reports one.
+    pub version: Option<String>,
+    pub username: String,
+    pub subscription: Option<String>,
+    /// How many aliases count towards the limit.
+    pub alias_count: u32,
+    /// The most aliases the account can have, if limited.
+    pub alias_limit: Option<u32>,
+    pub username_count: u32,
+    pub username_limit: Option<u32>,
+}
+
+impl AccountSummary {
+    /// Whether another alias can be created without going over the limit.
+    pub fn has_room_for_alias(&self) -> bool {
+        self.alias_limit
+            .is_none_or(|limit| self.alias_count < limit)
+    }
+}
+
+impl Display for AccountSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limit =
+            |limit: Option<u32>| limit.map_or("unlimited".to_string(), |limit| limit.to_string());
+        write!(f, "{} account {}", self.provider, self.username)?;
+        if let Some(subscription) = &self.subscription {
+            write!(f, " ({})", subscription)?;
+        }
+        write!(
+            f,
+            ": {} of {} aliases, {} of {} usernames.",
+            self.alias_count,
+            limit(self.alias_limit),
+            self.username_count,
+            limit(self.username_limit)
+        )
+    }
+}
+
+/// Which aliases to list, filtered by the service where it can.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AliasQuery {
+    /// Only active (`true`) or inactive (`false`) aliases, or both if not set.
+    pub active: Option<bool>,
+    pub deleted: DeletedAliases,
+    /// Text the email address or description contains.
+    pub search: Option<String>,
+    /// Only aliases on these domains, or on any domain if empty.
+    pub domains: Vec<String>,
+}
+
+/// Whether deleted aliases are listed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeletedAliases {
+    #[default]
+    Exclude,
+    Include,
+    Only,
+}
+
+impl std::str::FromStr for DeletedAliases {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "exclude" => Ok(DeletedAliases::Exclude),
+            "include" => Ok(DeletedAliases::Include),
+            "only" => Ok(DeletedAliases::Only),
+            _ => Err("must be one of \"exclude\", \"include\" or \"only\"".to_string()),
+        }
+    }
+}
+
+impl AliasQuery {
+    /// Whether the email address is on one of the domains asked for.
+    pub fn matches_domain(&self, email: &str) -> bool {
+        let domain = email.rsplit_once('@').map_or("", |(_, domain)| domain);
+        self.domains.is_empty()
+            || self
+                .domains
+                .iter()
+                .any(|expected| expected.eq_ignore_ascii_case(domain))
+    }
+}
+
+/// A domain that addresses can be made up on, creating aliases as mail arrives if catch-all is on.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AliasDomain {
+    pub id: String,
+    pub domain: String,
+    pub kind: DomainKind,
+    pub active: bool,
+    pub catch_all: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DomainKind {
+    /// A subdomain of the service's own domain, named after one of the account's usernames.
+    Username,
+    /// A domain the account owner has brought to the service.
+    Custom,
+}
+
+#[async_trait]
+pub trait AliasService: Send + Sync {
+    /// Describes the account the aliases belong to.
+    async fn get_account_summary(&self) -> Result<AccountSummary, ApiError>;
+
+    async fn get_aliases(&self, query: &AliasQuery) -> Result<Vec<Box<dyn Alias>>, ApiError>;
+
+    /// Gets a single alias by its ID, returning a not found error if there is none.
+    async fn get_alias(&self, id: &str) -> Result<Box<dyn Alias>, ApiError>;
+
+    /// Lists the username and custom domains of the account.
+    async fn get_domains(&self) -> Result<Vec<AliasDomain>, ApiError>;
+
+    async fn deactivate_alias(&self, id: &str) -> Result<(), ApiError>;
+
+    /// Makes a deactivated alias forward emails again.
+    async fn reactivate_alias(&self, id: &str) -> Result<(), ApiError>;
+
+    /// Removes the alias altogether.
+    async fn delete_alias(&self, id: &str) -> Result<(), ApiError>;
+
+    /// Replaces the description of the alias.
+    async fn set_description(&self, id: &str, description: &str) -> Result<(), ApiError>;
+
+    /// Creates a new alias to take the place of the given one, with the same description and, where the service
+    /// allows, on the same domain.
+    ///
+    /// This is not idempotent, so callers should not retry it.
+    async fn create_replacement(&self, alias: &dyn Alias) -> Result<Box<dyn Alias>, ApiError>;
+
+    /// Creates an alias for an address on a catch-all domain, for the caller to deactivate so mail to it is discarded
+    /// rather than creating an active alias.
+    ///
+    /// Deactivating is left to the caller so that it can be retried without creating the alias again.
+    async fn create_blocked_alias(
+        &self,
+        domain: &AliasDomain,
+        local_part: &str,
+    ) -> Result<Box<dyn Alias>, ApiError>;
+
+    /// Turns off catch-all for the domain, so only addresses that already have an alias receive mail.
+    async fn disable_catch_all(&self, domain: &AliasDomain) -> Result<(), ApiError>;
+}
+
+#[cfg(test)]
+pub mod fakes {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    pub struct FakeAlias {
+        pub id: String,
+        pub email: String,
+        pub description: Option<String>,
+        pub active: bool,
+        pub deleted: bool,
+    }
+
+    impl FakeAlias {
+        pub fn new(id: &str, email: &str) -> Self {
+            FakeAlias {
+                id: id.to_string(),
+                email: email.to_string(),
+                description: None,
+                active: true,
+                deleted: false,
+            }
+        }
+    }
+
+    impl Alias for FakeAlias {
+        fn is_active(&self) -> bool {
+            self.active
+        }
+
+        fn is_deleted(&self) -> bool {
+            self.deleted
+        }
+
+        fn get_id(&self) -> &str {
+            &self.id
+        }
+
+        fn get_email(&self) -> &str {
+            &self.email
+        }
+
+        fn get_description(&self) -> Option<&str> {
+            self.description.as_deref()
+        }
+
+        fn get_provider(&self) -> &str {
+            "Fake"
+        }
+
+        fn get_recipients(&self) -> Vec<&str> {
+            vec![]
+        }
+
+        fn get_created_at(&self) -> Option<DateTime<Utc>> {
+            None
+        }
+
+        fn get_updated_at(&self) -> Option<DateTime<Utc>> {
+            None
+        }
+
+        fn get_counters(&self) -> AliasCounters {
+            AliasCounters::default()
+        }
+    }
+
+    /// An in memory alias service that records what has been done to it.
+    #[derive(Debug, Default)]
+    pub struct FakeAliasService {
+        pub aliases: Vec<FakeAlias>,
+        pub deactivated: Mutex<Vec<String>>,
+        /// IDs of aliases that always fail to be changed, as if the service were unavailable.
+        pub failing: Vec<String>,
+        /// Every attempt to deactivate an alias, including those that failed.
+        pub deactivation_attempts: Mutex<Vec<String>>,
+        pub reactivated: Mutex<Vec<String>>,
+        pub deleted: Mutex<Vec<String>>,
+        /// The ID and new description of every alias whose description was set.
+        pub descriptions: Mutex<Vec<(String, String)>>,
+        /// The replacements created, with IDs of the form `<old ID>-replacement`.
+        pub replacements: Mutex<Vec<FakeAlias>>,
+        /// IDs of the aliases a replacement was attempted for, including those that failed.
+        pub replacement_attempts: Mutex<Vec<String>>,
+        pub account: Option<AccountSummary>,
+        pub domains: Vec<AliasDomain>,
+        /// Email addresses of the inactive aliases created to block them.
+        pub blocked: Mutex<Vec<String>>,
+        /// IDs of the domains catch-all was turned off for.
+        pub catch_all_disabled: Mutex<Vec<String>>,
+    }
+
+    impl FakeAliasService {
+        pub fn new(aliases: Vec<FakeAlias>) -> Self {
+            FakeAliasService {
+                aliases,
+                ..Default::default()
+            }
+        }
+
+        fn fail_if_failing(&self, id: &str) -> Result<(), ApiError> {
+            if self.failing.iter().any(|failing| failing == id) {
+                return Err(ApiError::Unavailable {
+                    status: 503,
+                    body: String::new(),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl AliasService for FakeAliasService {
+        async fn get_account_summary(&self) -> Result<AccountSummary, ApiError> {
+            self.account.clone().ok_or_else(|| ApiError::NotFound {
+                status: 404,
+                body: String::new(),
+            })
+        }
+
+        /// Filters on everything but whether the alias is deleted, as fake aliases cannot be.
+        async fn get_aliases(&self, query: &AliasQuery) -> Result<Vec<Box<dyn Alias>>, ApiError> {
+            Ok(self
+                .aliases
+                .iter()
+                .filter(|alias| query.active.is_none_or(|active| alias.active == active))
+                .filter(|alias| {
+                    query.search.as_ref().is_none_or(|search| {
+                        alias.email.contains(search)
+                            || alias
+                                .description
+                                .as_ref()
+                                .is_some_and(|description| description.contains(search))
+                    })
+                })
+                .filter(|alias| query.matches_domain(&alias.email))
+                .map(|alias| {
+                    let boxed_alias: Box<dyn Alias> = Box::new(alias.clone());
+                    boxed_alias
+                })
+                .collect())
+        }
+
+        async fn get_alias(&self, id: &str) -> Result<Box<dyn Alias>, ApiError> {
+            self.aliases
+                .iter()
+                .find(|alias| alias.id == id)
+                .map(|alias| {
+                    let boxed_alias: Box<dyn Alias> = Box::new(alias.clone());
+                    boxed_alias
+                })
+                .ok_or_else(|| ApiError::NotFound {
+                    status: 404,
+                    body: String::new(),
+                })
+        }
+
+        async fn get_domains(&self) -> Result<Vec<AliasDomain>, ApiError> {
+            Ok(self.domains.clone())
+        }
+
+        async fn deactivate_alias(&self, id: &str) -> Result<(), ApiError> {
+            self.deactivation_attempts
+                .lock()
+                .unwrap()
+                .push(id.to_string());
+            self.fail_if_failing(id)?;
+            self.deactivated.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+
+        async fn reactivate_alias(&self, id: &str) -> Result<(), ApiError> {
+            self.fail_if_failing(id)?;
+            self.reactivated.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+
+        async fn delete_alias(&self, id: &str) -> Result<(), ApiError> {
+            self.fail_if_failing(id)?;
+            self.deleted.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+
+        async fn set_description(&self, id: &str, description: &str) -> Result<(), ApiError> {
+            self.fail_if_failing(id)?;
+            self.descriptions
+                .lock()
+                .unwrap()
+                .push((id.to_string(), description.to_string()));
+            Ok(())
+        }
+
+        async fn create_replacement(&self, alias: &dyn Alias) -> Result<Box<dyn Alias>, ApiError> {
+            self.replacement_attempts
+                .lock()
+                .unwrap()
+                .push(alias.get_id().to_string());
+            self.fail_if_failing(alias.get_id())?;
+            let domain = alias.get_domain();
+            let replacement = FakeAlias {
+                description: alias.get_description().map(str::to_string),
+                ..FakeAlias::new(
+                    &format!("{}-replacement", alias.get_id()),
+                    &format!("replacement@{}", domain),
+                )
+            };
+            self.replacements.lock().unwrap().push(replacement.clone());
+            Ok(Box::new(replacement))
+        }
+
+        async fn create_blocked_alias(
+            &self,
+            domain: &AliasDomain,
+            local_part: &str,
+        ) -> Result<Box<dyn Alias>, ApiError> {
+            let email = format!("{}@{}", local_part, domain.domain);
+            self.fail_if_failing(&email)?;
+            self.blocked.lock().unwrap().push(email.clone());
+            Ok(Box::new(FakeAlias::new(
+                &format!("{}-blocked", email),
+                &email,
+            )))
+        }
+
+        async fn disable_catch_all(&self, domain: &AliasDomain) -> Result<(), ApiError> {
+            self.fail_if_failing(&domain.id)?;
+            self.catch_all_disabled
+                .lock()
+                .unwrap()
+                .push(domain.id.clone());
+            Ok(())
+        }
     }
 }
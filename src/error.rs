@@ -0,0 +1,290 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+/// An error from calling the API of an alias service or haveibeenpwned.com.
+///
+/// Where there was a response, its status code and body are kept so callers can decide whether to retry, skip or
+/// abort.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The token was missing or rejected.
+    Unauthorized { status: u16, body: String },
+    /// The token was accepted, but the request was refused, e.g. for a domain that is not verified or an account at
+    /// its alias limit.
+    Forbidden { status: u16, body: String },
+    /// The requested resource does not exist.
+    NotFound { status: u16, body: String },
+    /// Too many requests have been made, optionally with how long to wait before trying again.
+    RateLimited {
+        status: u16,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    /// The service had a problem of its own (a 5xx response).
+    Unavailable { status: u16, body: String },
+    /// Any other response that was not expected.
+    UnexpectedStatus { status: u16, body: String },
+    /// The response could not be decoded.
+    Decode {
+        status: u16,
+        body: String,
+        source: serde_json::Error,
+    },
+    /// The request could not be made or the response could not be read.
+    Network { source: reqwest::Error },
+    /// The account cannot have any more aliases, so the request was not made.
+    LimitReached { limit: u32 },
+}
+
+impl ApiError {
+    /// Creates the error for an unexpected response, based on its status code.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(source) => return ApiError::Network { source },
+        };
+        match status {
+            401 => ApiError::Unauthorized { status, body },
+            403 => ApiError::Forbidden { status, body },
+            404 => ApiError::NotFound { status, body },
+            429 => ApiError::RateLimited {
+                status,
+                body,
+                retry_after,
+            },
+            500..=599 => ApiError::Unavailable { status, body },
+            _ => ApiError::UnexpectedStatus { status, body },
+        }
+    }
+
+    /// The status code of the response, if there was one.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ApiError::Unauthorized { status, .. }
+            | ApiError::Forbidden { status, .. }
+            | ApiError::NotFound { status, .. }
+            | ApiError::RateLimited { status, .. }
+            | ApiError::Unavailable { status, .. }
+            | ApiError::UnexpectedStatus { status, .. }
+            | ApiError::Decode { status, .. } => Some(*status),
+            ApiError::Network { source } => source.status().map(|status| status.as_u16()),
+            ApiError::LimitReached { .. } => None,
+        }
+    }
+
+    /// Whether the same request may succeed if tried again later.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ApiError::RateLimited { .. } | ApiError::Unavailable { .. } | ApiError::Network { .. }
+        )
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Unauthorized { status, body } => {
+                write!(f, "{} - Authentication failed. {}", status, body)
+            }
+            ApiError::Forbidden { status, body } => write!(f, "{} - Forbidden. {}", status, body),
+            ApiError::NotFound { status, body } => write!(f, "{} - Not found. {}", status, body),
+            ApiError::RateLimited {
+                status,
+                body,
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "{} - Rate limited, retry after {} seconds. {}",
+                status,
+                retry_after.as_secs(),
+                body
+            ),
+            ApiError::RateLimited { status, body, .. } => {
+                write!(f, "{} - Rate limited. {}", status, body)
+            }
+            ApiError::Unavailable { status, body } => {
+                write!(f, "{} - Service unavailable. {}", status, body)
+            }
+            ApiError::UnexpectedStatus { status, body } => {
+                write!(f, "{} - Unexpected response. {}", status, body)
+            }
+            ApiError::Decode {
+                status,
+                body,
+                source,
+            } => write!(
+                f,
+                "{} - Unable to decode response: {}. {}",
+                status, source, body
+            ),
+            ApiError::Network { source } => write!(f, "Request failed: {}", source),
+            ApiError::LimitReached { limit } => {
+                write!(f, "The account has reached its limit of {} aliases.", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Decode { source, .. } => Some(source),
+            ApiError::Network { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(source: reqwest::Error) -> Self {
+        ApiError::Network { source }
+    }
+}
+
+/// Reads the body of the response and decodes it from JSON, keeping the body if it cannot be decoded.
+pub async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ApiError> {
+    let status = response.status().as_u16();
+    let body = response.text().await?;
+    serde_json::from_str(&body).map_err(|source| ApiError::Decode {
+        status,
+        body,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use httpmock::prelude::*;
+
+    use super::*;
+
+    /// Returns a response from a mock server, along with the server so it is kept running while the body is read.
+    async fn response(
+        status: u16,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> (MockServer, reqwest::Response) {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/");
+                let mut then = then.status(status).body(body);
+                for (name, value) in headers {
+                    then = then.header(*name, *value);
+                }
+            })
+            .await;
+        let response = reqwest::get(server.url("/")).await.unwrap();
+        (server, response)
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_unauthorized_for_401() {
+        let (_server, response) = response(401, &[], "Unauthenticated.").await;
+
+        let error = ApiError::from_response(response).await;
+
+        match &error {
+            ApiError::Unauthorized { status, body } => {
+                assert_eq!(*status, 401);
+                assert_eq!(body, "Unauthenticated.");
+            }
+            _ => panic!("Error returned was not Unauthorized!"),
+        }
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_forbidden_for_403() {
+        let (_server, response) = response(403, &[], "Domain not verified").await;
+
+        let error = ApiError::from_response(response).await;
+
+        assert!(matches!(error, ApiError::Forbidden { status: 403, .. }));
+        assert!(!error.is_transient());
+        assert_eq!(error.to_string(), "403 - Forbidden. Domain not verified");
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_not_found_for_404() {
+        let (_server, response) = response(404, &[], "").await;
+
+        let error = ApiError::from_response(response).await;
+
+        assert!(matches!(error, ApiError::NotFound { status: 404, .. }));
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_rate_limited_with_retry_after_for_429() {
+        let (_server, response) = response(429, &[("retry-after", "2")], "Slow down").await;
+
+        let error = ApiError::from_response(response).await;
+
+        match &error {
+            ApiError::RateLimited { retry_after, .. } => {
+                assert_eq!(*retry_after, Some(Duration::from_secs(2)))
+            }
+            _ => panic!("Error returned was not RateLimited!"),
+        }
+        assert_eq!(error.status(), Some(429));
+        assert!(error.is_transient());
+        assert_eq!(
+            error.to_string(),
+            "429 - Rate limited, retry after 2 seconds. Slow down"
+        );
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_unavailable_for_5xx() {
+        let (_server, response) = response(503, &[], "").await;
+
+        let error = ApiError::from_response(response).await;
+
+        assert!(matches!(error, ApiError::Unavailable { status: 503, .. }));
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn from_response_returns_unexpected_status_for_other() {
+        let (_server, response) = response(400, &[], "Bad request").await;
+
+        let error = ApiError::from_response(response).await;
+
+        assert!(matches!(
+            error,
+            ApiError::UnexpectedStatus { status: 400, .. }
+        ));
+        assert_eq!(error.to_string(), "400 - Unexpected response. Bad request");
+    }
+
+    #[tokio::test]
+    async fn decode_returns_decode_error_with_body_and_source() {
+        let (_server, response) = response(200, &[], "not json").await;
+
+        let result = decode::<Vec<String>>(response).await;
+
+        let error = result.unwrap_err();
+        match &error {
+            ApiError::Decode { status, body, .. } => {
+                assert_eq!(*status, 200);
+                assert_eq!(body, "not json");
+            }
+            _ => panic!("Error returned was not Decode!"),
+        }
+        assert!(error.source().is_some());
+        assert!(!error.is_transient());
+    }
+}
@@ -0,0 +1,127 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::email_alias::Alias;
+
+/// Which aliases are scanned, so that some (e.g. shared team inboxes) are never checked or acted on.
+#[derive(Debug, Clone, Default)]
+pub struct AliasFilter {
+    /// If not empty, only aliases matching one of these rules are scanned.
+    pub include: Vec<AliasRule>,
+    /// Aliases matching any of these rules are not scanned, even if included.
+    pub exclude: Vec<AliasRule>,
+}
+
+impl AliasFilter {
+    pub fn allows(&self, alias: &dyn Alias) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|rule| rule.matches(alias)))
+            && !self.exclude.iter().any(|rule| rule.matches(alias))
+    }
+}
+
+/// Matches aliases on every one of the conditions given.
+#[derive(Debug, Clone, Default)]
+pub struct AliasRule {
+    pub id: Option<String>,
+    /// Patterns the whole email address must match, ignoring case.
+    pub emails: Vec<Regex>,
+    /// The domain of the email address, ignoring case.
+    pub domain: Option<String>,
+    /// Text the description must contain, ignoring case.
+    pub description: Option<String>,
+}
+
+impl AliasRule {
+    pub fn matches(&self, alias: &dyn Alias) -> bool {
+        let email = alias.get_email();
+        let domain = alias.get_domain();
+        self.id.as_ref().is_none_or(|id| id == alias.get_id())
+            && self.emails.iter().all(|pattern| pattern.is_match(email))
+            && self
+                .domain
+                .as_ref()
+                .is_none_or(|expected| expected.eq_ignore_ascii_case(domain))
+            && self.description.as_ref().is_none_or(|text| {
+                alias
+                    .get_description()
+                    .unwrap_or("")
+                    .to_lowercase()
+                    .contains(&text.to_lowercase())
+            })
+    }
+}
+
+/// Compiles a glob, where `*` matches any number of characters and `?` matches one, into a case insensitive regex
+/// matching the whole address.
+pub fn glob(pattern: &str) -> Result<Regex, regex::Error> {
+    let expression: String = pattern
+        .chars()
+        .map(|character| match character {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            character => regex::escape(&character.to_string()),
+        })
+        .collect();
+    regex(&format!("^{}$", expression))
+}
+
+/// Compiles a case insensitive regex.
+pub fn regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_alias::fakes::FakeAlias;
+
+    fn test_alias() -> FakeAlias {
+        FakeAlias {
+            description: Some("Team Inbox".to_string()),
+            ..FakeAlias::new("test-id", "support@team.example.com")
+        }
+    }
+
+    #[test]
+    fn glob_matches_whole_address() {
+        let pattern = glob("support@*.example.com").unwrap();
+
+        assert!(pattern.is_match("Support@team.example.com"));
+        assert!(!pattern.is_match("support@team.example.com.evil"));
+        assert!(!glob("s?pport@*").unwrap().is_match("spport@example.com"));
+    }
+
+    #[test]
+    fn rule_matches_every_condition() {
+        let rule = AliasRule {
+            domain: Some("TEAM.example.com".to_string()),
+            description: Some("inbox".to_string()),
+            ..Default::default()
+        };
+        assert!(rule.matches(&test_alias()));
+
+        let rule = AliasRule {
+            id: Some("other-id".to_string()),
+            ..rule
+        };
+        assert!(!rule.matches(&test_alias()));
+    }
+
+    #[test]
+    fn filter_excludes_after_including() {
+        let mut filter = AliasFilter::default();
+        assert!(filter.allows(&test_alias()));
+
+        filter.include = vec![AliasRule {
+            emails: vec![regex("^support@").unwrap()],
+            ..Default::default()
+        }];
+        assert!(filter.allows(&test_alias()));
+        assert!(!filter.allows(&FakeAlias::new("other", "other@team.example.com")));
+
+        filter.exclude = vec![AliasRule {
+            id: Some("test-id".to_string()),
+            ..Default::default()
+        }];
+        assert!(!filter.allows(&test_alias()));
+    }
+}
@@ -1,9 +1,17 @@
-use std::{fmt::Display, thread, time};
+use std::collections::BTreeMap;
 
-use log::debug;
-use serde::Deserialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+use crate::config::{ConfigError, Problem};
+use crate::error::{decode, ApiError};
+use crate::metrics;
+use crate::redact;
+use crate::secret::Secret;
+
+/// A breach as described by Have I Been Pwned, serialised in the same shape it is read in, other than times being
+/// written in full RFC 3339.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Breach {
     #[serde(rename = "Name")]
     name: String,
@@ -12,13 +20,13 @@ pub struct Breach {
     #[serde(rename = "Domain")]
     domain: String,
     #[serde(rename = "BreachDate")]
-    breach_date: String,
-    #[serde(rename = "AddedDate")]
-    added_date: String,
-    #[serde(rename = "ModifiedDate")]
-    modified_date: String,
+    breach_date: NaiveDate,
+    #[serde(rename = "AddedDate", deserialize_with = "hibp_time::deserialize")]
+    added_date: DateTime<Utc>,
+    #[serde(rename = "ModifiedDate", deserialize_with = "hibp_time::deserialize")]
+    modified_date: DateTime<Utc>,
     #[serde(rename = "PwnCount")]
-    pwn_count: i32,
+    pwn_count: u64,
     #[serde(rename = "Description")]
     description: String,
     #[serde(rename = "DataClasses")]
@@ -37,110 +45,191 @@ pub struct Breach {
     logo_path: String,
 }
 
+/// Only the name is needed by scans, the rest are kept for anything exporting the results.
+#[allow(dead_code)]
+impl Breach {
+    /// The name that identifies the breach, e.g. `Adobe`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the breach for display, e.g. `Battlefield Heroes`.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The domain of the breached service, which may be empty.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The day the breach happened, which may only be accurate to the month or year.
+    pub fn breach_date(&self) -> NaiveDate {
+        self.breach_date
+    }
+
+    /// When the breach was added to Have I Been Pwned.
+    pub fn added_date(&self) -> DateTime<Utc> {
+        self.added_date
+    }
+
+    pub fn modified_date(&self) -> DateTime<Utc> {
+        self.modified_date
+    }
+
+    /// How many accounts were in the breach.
+    pub fn pwn_count(&self) -> u64 {
+        self.pwn_count
+    }
+
+    /// A description of the breach, in HTML.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The kinds of data in the breach, e.g. `Passwords`.
+    pub fn data_classes(&self) -> &[String] {
+        &self.data_classes
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.is_verified
+    }
+
+    pub fn is_fabricated(&self) -> bool {
+        self.is_fabricated
+    }
+
+    pub fn is_sensitive(&self) -> bool {
+        self.is_sensitive
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.is_retired
+    }
+
+    pub fn is_spam_list(&self) -> bool {
+        self.is_spam_list
+    }
+
+    pub fn logo_path(&self) -> &str {
+        &self.logo_path
+    }
+}
+
+/// Reads the times Have I Been Pwned gives, which are in UTC and to the minute, e.g. `2013-12-04T00:00Z`, along with
+/// full RFC 3339 times, as breaches are written out in.
+mod hibp_time {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        NaiveDateTime::parse_from_str(&time, "%Y-%m-%dT%H:%MZ")
+            .map(|time| time.and_utc())
+            .or_else(|_| DateTime::parse_from_rfc3339(&time).map(|time| time.to_utc()))
+            .map_err(|error| D::Error::custom(format!("invalid time {:?}: {}", time, error)))
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct HIBP<'a> {
     client: &'a reqwest::Client,
-    token: String,
+    token: Secret,
     host: String,
 }
 
 impl<'a> HIBP<'a> {
     /// Creates a new instance to query against haveibeenpwned.com.
     ///
-    /// For this to work, a `HIBP_TOKEN` environment variable must be set. If it is not set, this will panic.
+    /// For this to work, a token must be given. If it is not, a [`ConfigError`] is returned. See
+    /// [`Config::read_credentials`](crate::config::Config::read_credentials) for where it is read from.
     ///
     /// # Examples
     /// ```
     /// let client = reqwest::Client::new();
-    /// std::env::set_var("HIBP_TOKEN", "test-token");
-    /// let hibp = HIBP::new(&client);
+    /// let hibp = HIBP::new(&client, Some(Secret::new("test-token")))?;
     /// ```
-    pub fn new(client: &'a reqwest::Client) -> Self {
-        let token = std::env::var("HIBP_TOKEN").expect("Please provide HIBP_TOKEN");
-        HIBP {
+    pub fn new(client: &'a reqwest::Client, token: Option<Secret>) -> Result<Self, ConfigError> {
+        let token = token
+            .ok_or_else(|| ConfigError::new(vec![Problem::missing("hibp.token", "HIBP_TOKEN")]))?;
+        Ok(HIBP {
             client,
             token,
             host: "https://haveibeenpwned.com".to_string(),
+        })
+    }
+
+    /// Creates an instance that queries the given host instead, such as a mock server.
+    #[cfg(test)]
+    pub fn with_host(client: &'a reqwest::Client, host: &str) -> Self {
+        HIBP {
+            client,
+            token: Secret::new("test-token"),
+            host: host.to_string(),
         }
     }
 
-    pub async fn get_breaches(
-        &self,
-        email_address: &str,
-    ) -> Result<Vec<Breach>, Box<dyn std::error::Error>> {
+    /// Gets all the breaches the email address has been found in.
+    ///
+    /// If rate limited, [`ApiError::RateLimited`] is returned with how long haveibeenpwned.com asks to wait, for the
+    /// caller's [`RetryPolicy`](crate::scan::RetryPolicy) to wait for.
+    pub async fn get_breaches(&self, email_address: &str) -> Result<Vec<Breach>, ApiError> {
         let url = &format!(
             "{}/api/v3/breachedaccount/{}?truncateResponse=false",
             &(self.host),
             email_address
         );
-        let response = self
-            .client
-            .get(url)
-            .header("hibp-api-key", &(self.token))
-            .header("user-agent", "has-my-alias-been-pwned")
-            .send()
-            .await?;
+        breaches_from(self.send(url).await?).await
+    }
+
+    /// Gets the breached addresses on a domain, as a map of the part before the `@` to the names of the breaches it
+    /// was found in. The domain has to have been verified on haveibeenpwned.com with the same API key.
+    ///
+    /// If rate limited, [`ApiError::RateLimited`] is returned with how long haveibeenpwned.com asks to wait, for the
+    /// caller's [`RetryPolicy`](crate::scan::RetryPolicy) to wait for.
+    pub async fn search_domain(
+        &self,
+        domain: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>, ApiError> {
+        let url = &format!("{}/api/v3/breacheddomain/{}", &(self.host), domain);
+        let response = self.send(url).await?;
         if response.status() == 404 {
-            return Ok(vec![]);
-        }
-        if response.status() == 429 {
-            let retry_after = response
-                .headers()
-                .get("retry-after")
-                .unwrap()
-                .to_str()?
-                .parse::<u64>()?;
-            let duration = time::Duration::from_secs(retry_after);
-            debug!("Need to wait {} seconds.", duration.as_secs());
-            thread::sleep(duration);
-            let response = self
-                .client
-                .get(url)
-                .header("hibp-api-key", &(self.token))
-                .header("user-agent", "has-my-alias-been-pwned")
-                .send()
-                .await?;
-            if response.status() == 404 {
-                return Ok(vec![]);
-            }
-            let breaches = response.json::<Vec<Breach>>().await?;
-            return Ok(breaches);
+            return Ok(BTreeMap::new());
         }
         if response.status() != 200 {
-            return Err(Box::new(HIBPError::new(
-                "Failed to get breaches.".to_string(),
-                response.status().as_u16(),
-            )));
+            return Err(ApiError::from_response(response).await);
         }
-        let breaches = response.json::<Vec<Breach>>().await?;
-        Ok(breaches)
+        decode(response).await
     }
-}
-
-#[derive(Debug, Clone)]
-pub struct HIBPError {
-    pub message: String,
-    pub status_code: u16,
-}
 
-impl HIBPError {
-    pub fn new(message: String, status_code: u16) -> Self {
-        HIBPError {
-            message,
-            status_code,
-        }
+    async fn send(&self, url: &str) -> Result<reqwest::Response, ApiError> {
+        let response = self
+            .client
+            .get(url)
+            .header("hibp-api-key", self.token.expose())
+            .header("user-agent", "has-my-alias-been-pwned")
+            .send()
+            .await
+            .map_err(redact::network_error)?;
+        metrics::HIBP_REQUESTS
+            .with_label_values(&[response.status().as_str()])
+            .inc();
+        Ok(response)
     }
 }
 
-impl Display for HIBPError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} - {}", self.status_code, self.message)
+/// haveibeenpwned.com responds with 404 if the email address has not been found in any breaches.
+async fn breaches_from(response: reqwest::Response) -> Result<Vec<Breach>, ApiError> {
+    if response.status() == 404 {
+        return Ok(vec![]);
     }
-}
-
-impl std::error::Error for HIBPError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+    if response.status() != 200 {
+        return Err(ApiError::from_response(response).await);
     }
+    decode::<Vec<Breach>>(response).await
 }
 
 #[cfg(test)]
@@ -150,38 +239,67 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn breach_serialises_in_the_shape_it_is_read() {
+        let json = std::fs::read_to_string("resources/test/hibp_breaches.json").unwrap();
+        let breaches: Vec<Breach> = serde_json::from_str(&json).unwrap();
+
+        let exported = serde_json::to_string(&breaches).unwrap();
+        let imported: Vec<Breach> = serde_json::from_str(&exported).unwrap();
+
+        assert_eq!(imported, breaches);
+        let adobe = &imported[0];
+        assert_eq!(adobe.title(), "Adobe");
+        assert_eq!(adobe.domain(), "adobe.com");
+        assert_eq!(
+            adobe.breach_date(),
+            NaiveDate::from_ymd_opt(2013, 10, 4).unwrap()
+        );
+        assert_eq!(
+            adobe.added_date(),
+            "2013-12-04T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(serde_json::to_value(adobe).unwrap()["AddedDate"]
+            .as_str()
+            .unwrap()
+            .starts_with("2013-12-04T00:00:00"));
+        assert_eq!(adobe.pwn_count(), 152445165);
+        assert!(adobe.data_classes().contains(&"Passwords".to_string()));
+        assert!(adobe.is_verified());
+        assert!(!adobe.is_spam_list());
+    }
+
     #[tokio::test]
-    #[serial]
-    #[should_panic(expected = "Please provide HIBP_TOKEN: NotPresent")]
-    async fn new_throw_error_if_token_variable_not_set() {
+    async fn new_return_error_if_token_not_given() {
         let client = reqwest::Client::new();
-        std::env::remove_var("HIBP_TOKEN");
-        HIBP::new(&client);
+
+        let hibp = HIBP::new(&client, None);
+
+        assert_eq!(
+            hibp.err().unwrap().problems,
+            vec![Problem::missing("hibp.token", "HIBP_TOKEN")]
+        );
     }
 
     #[tokio::test]
-    #[serial]
-    async fn new_return_instance_if_token_variable_empty() {
+    async fn new_return_instance_if_token_empty() {
         let client = reqwest::Client::new();
-        std::env::set_var("HIBP_TOKEN", "");
 
-        let hibp = HIBP::new(&client);
+        let hibp = HIBP::new(&client, Some(Secret::new(""))).unwrap();
 
         assert_eq!(hibp.client as *const _, &client as *const _);
-        assert_eq!(hibp.token, "");
+        assert_eq!(hibp.token.expose(), "");
         assert_eq!(hibp.host, "https://haveibeenpwned.com".to_string());
     }
 
     #[tokio::test]
-    #[serial]
-    async fn new_return_instance_if_token_variable_has_value() {
+    async fn new_return_instance_if_token_has_value() {
         let client = reqwest::Client::new();
-        std::env::set_var("HIBP_TOKEN", "test-token");
 
-        let hibp = HIBP::new(&client);
+        let hibp = HIBP::new(&client, Some(Secret::new("test-token"))).unwrap();
 
         assert_eq!(hibp.client as *const _, &client as *const _);
-        assert_eq!(hibp.token, "test-token");
+        assert_eq!(hibp.token.expose(), "test-token");
         assert_eq!(hibp.host, "https://haveibeenpwned.com".to_string());
     }
 
@@ -191,7 +309,7 @@ mod tests {
         let client = reqwest::Client::new();
         let hibp = HIBP {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: "http://localhost".to_string(),
         };
 
@@ -199,9 +317,9 @@ mod tests {
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &reqwest::Error = match error.downcast_ref::<reqwest::Error>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an reqwest::Error!"),
+        let actual_error: &reqwest::Error = match &error {
+            ApiError::Network { source } => source,
+            _ => panic!("Error returned was not an ApiError::Network!"),
         };
         assert!(actual_error.is_request());
     }
@@ -222,7 +340,7 @@ mod tests {
         let client = reqwest::Client::new();
         let hibp = HIBP {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
@@ -230,11 +348,10 @@ mod tests {
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &HIBPError = match error.downcast_ref::<HIBPError>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an HIBPError!"),
-        };
-        assert_eq!(actual_error.message, "Failed to get breaches.");
+        assert!(matches!(
+            error,
+            ApiError::UnexpectedStatus { status: 400, .. }
+        ));
 
         breaches_mock.assert();
     }
@@ -255,7 +372,7 @@ mod tests {
         let client = reqwest::Client::new();
         let hibp = HIBP {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
@@ -263,11 +380,7 @@ mod tests {
 
         assert!(response.is_err());
         let error = response.unwrap_err();
-        let actual_error: &reqwest::Error = match error.downcast_ref::<reqwest::Error>() {
-            Some(error) => error,
-            None => panic!("Error returned was not an reqwest::Error!"),
-        };
-        assert!(actual_error.is_decode());
+        assert!(matches!(error, ApiError::Decode { status: 200, .. }));
 
         breaches_mock.assert();
     }
@@ -291,7 +404,7 @@ mod tests {
         let client = reqwest::Client::new();
         let hibp = HIBP {
             client: &client,
-            token: "test-token".to_string(),
+            token: Secret::new("test-token"),
             host: server.url(""),
         };
 
@@ -301,4 +414,139 @@ mod tests {
 
         breaches_mock.assert();
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_breaches_returns_unauthorized_for_401() {
+        let server = MockServer::start();
+        let breaches_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/v3/breachedaccount/email@email.com");
+            then.status(401)
+                .body("Access denied due to improperly formed hibp-api-key.");
+        });
+
+        let client = reqwest::Client::new();
+        let hibp = HIBP {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = hibp.get_breaches("email@email.com").await;
+
+        let error = response.unwrap_err();
+        match &error {
+            ApiError::Unauthorized { status, body } => {
+                assert_eq!(*status, 401);
+                assert_eq!(body, "Access denied due to improperly formed hibp-api-key.");
+            }
+            _ => panic!("Error returned was not an ApiError::Unauthorized!"),
+        }
+
+        breaches_mock.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_breaches_returns_rate_limited_without_waiting() {
+        let server = MockServer::start();
+        let breaches_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/v3/breachedaccount/email@email.com");
+            then.status(429).header("retry-after", "0");
+        });
+
+        let client = reqwest::Client::new();
+        let hibp = HIBP {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = hibp.get_breaches("email@email.com").await;
+
+        assert!(matches!(
+            response.unwrap_err(),
+            ApiError::RateLimited { status: 429, .. }
+        ));
+
+        breaches_mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_breaches_returns_empty_for_not_found() {
+        let server = MockServer::start();
+        let breaches_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/v3/breachedaccount/email@email.com");
+            then.status(404);
+        });
+
+        let client = reqwest::Client::new();
+        let hibp = HIBP {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let breaches = hibp.get_breaches("email@email.com").await;
+
+        assert!(breaches.unwrap().is_empty());
+
+        breaches_mock.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn search_domain_returns_breached_addresses() {
+        let server = MockServer::start();
+        let domain_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/v3/breacheddomain/example.com")
+                .header("hibp-api-key", "test-token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"shop":["Adobe"],"forum":["Adobe","LinkedIn"]}"#);
+        });
+
+        let client = reqwest::Client::new();
+        let hibp = HIBP {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let addresses = hibp.search_domain("example.com").await.unwrap();
+
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses["forum"], vec!["Adobe", "LinkedIn"]);
+
+        domain_mock.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn search_domain_returns_forbidden_for_unverified_domain() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v3/breacheddomain/example.com");
+            then.status(403);
+        });
+
+        let client = reqwest::Client::new();
+        let hibp = HIBP {
+            client: &client,
+            token: Secret::new("test-token"),
+            host: server.url(""),
+        };
+
+        let response = hibp.search_domain("example.com").await;
+
+        assert!(matches!(
+            response.unwrap_err(),
+            ApiError::Forbidden { status: 403, .. }
+        ));
+    }
 }
@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::LevelFilter;
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::Append;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+
+/// How each log record is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    /// One JSON object per line.
+    Json,
+}
+
+/// The level to log at, overall and for individual modules, in the same form as `RUST_LOG`, e.g.
+/// `warn,has_my_alias_been_pwned=debug`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelFilters {
+    pub root: LevelFilter,
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LevelFilters {
+    fn default() -> Self {
+        LevelFilters {
+            root: LevelFilter::Info,
+            modules: vec![],
+        }
+    }
+}
+
+impl FromStr for LevelFilters {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse_level = |level: &str| {
+            LevelFilter::from_str(level.trim()).map_err(|_| {
+                format!(
+                    "has unknown level \"{}\", expected one of off, error, warn, info, debug or trace",
+                    level.trim()
+                )
+            })
+        };
+        let mut filters = LevelFilters::default();
+        for directive in value.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                _ if directive.is_empty() => {}
+                Some((module, level)) => filters
+                    .modules
+                    .push((module.trim().to_string(), parse_level(level)?)),
+                None => filters.root = parse_level(directive)?,
+            }
+        }
+        Ok(filters)
+    }
+}
+
+/// A log file, rolled over once it reaches the maximum size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLog {
+    pub path: PathBuf,
+    /// The size in bytes at which the file is rolled over.
+    pub max_size: u64,
+    /// How many rolled over files to keep, named `<path>.0` (the most recent) to `<path>.<keep - 1>`.
+    pub keep: u32,
+}
+
+impl FileLog {
+    pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_KEEP: u32 = 5;
+
+    pub fn new(path: PathBuf) -> Self {
+        FileLog {
+            path,
+            max_size: Self::DEFAULT_MAX_SIZE,
+            keep: Self::DEFAULT_KEEP,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoggingSettings {
+    pub levels: LevelFilters,
+    pub format: LogFormat,
+    /// Where to write the log, or stdout if not set.
+    pub file: Option<FileLog>,
+    /// Whether to mask email addresses and URLs, see [`crate::redact`].
+    pub privacy: bool,
+}
+
+/// Builds the log4rs configuration for the settings.
+pub fn build(settings: &LoggingSettings) -> Result<Config, Box<dyn std::error::Error>> {
+    let encoder: Box<dyn Encode> = match settings.format {
+        LogFormat::Plain => Box::new(PatternEncoder::default()),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    };
+    let appender: Box<dyn Append> = match &settings.file {
+        None => Box::new(ConsoleAppender::builder().encoder(encoder).build()),
+        Some(file) => {
+            let roller = FixedWindowRoller::builder()
+                .build(&format!("{}.{{}}", file.path.display()), file.keep)?;
+            let policy =
+                CompoundPolicy::new(Box::new(SizeTrigger::new(file.max_size)), Box::new(roller));
+            Box::new(
+                RollingFileAppender::builder()
+                    .encoder(encoder)
+                    .build(&file.path, Box::new(policy))?,
+            )
+        }
+    };
+    let loggers = settings
+        .levels
+        .modules
+        .iter()
+        .map(|(module, level)| Logger::builder().build(module, *level));
+    Ok(Config::builder()
+        .appender(Appender::builder().build("main", appender))
+        .loggers(loggers)
+        .build(Root::builder().appender("main").build(settings.levels.root))?)
+}
+
+/// Parses a size such as `10MB`, in bytes, with `KB`, `MB` and `GB` being powers of 1024.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return Err("must be a number of B, KB, MB or GB".to_string()),
+    };
+    number
+        .parse::<u64>()
+        .map(|number| number * multiplier)
+        .map_err(|_| "must be a number of B, KB, MB or GB".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn level_filters_parse_root_and_modules() {
+        let filters: LevelFilters = "warn, has_my_alias_been_pwned=debug,reqwest=off"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            filters,
+            LevelFilters {
+                root: LevelFilter::Warn,
+                modules: vec![
+                    ("has_my_alias_been_pwned".to_string(), LevelFilter::Debug),
+                    ("reqwest".to_string(), LevelFilter::Off),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn level_filters_default_root_to_info() {
+        let filters: LevelFilters = "hyper=error".parse().unwrap();
+
+        assert_eq!(filters.root, LevelFilter::Info);
+    }
+
+    #[test]
+    fn level_filters_return_error_for_unknown_level() {
+        let error = "loud".parse::<LevelFilters>().unwrap_err();
+
+        assert_eq!(
+            error,
+            "has unknown level \"loud\", expected one of off, error, warn, info, debug or trace"
+        );
+    }
+
+    #[test]
+    fn parse_size_accepts_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4KB"), Ok(4096));
+        assert_eq!(parse_size("10 mb"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1GB"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("10TB").is_err());
+    }
+
+    #[test]
+    fn build_creates_rolling_log_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("has-my-alias-been-pwned.log");
+
+        let config = build(&LoggingSettings {
+            format: LogFormat::Json,
+            file: Some(FileLog::new(path.clone())),
+            ..Default::default()
+        });
+
+        assert!(config.is_ok());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn build_adds_module_loggers() {
+        let config = build(&LoggingSettings {
+            levels: "error,has_my_alias_been_pwned=trace".parse().unwrap(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(config.root().level(), LevelFilter::Error);
+        assert_eq!(config.loggers()[0].name(), "has_my_alias_been_pwned");
+        assert_eq!(config.loggers()[0].level(), LevelFilter::Trace);
+    }
+}
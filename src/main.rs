@@ -1,64 +1,253 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
 use dotenv::dotenv;
-use log::{debug, error, info, warn, LevelFilter};
-use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Config, Root};
+use log::{error, info};
 
+use cli::{Cli, Command, ConfigCommand};
+use config::{ConfigError, Settings};
 use email_alias::AliasService;
+use hibp::HIBP;
+use scan::Scanner;
+use secret::Secret;
+use shutdown::Shutdown;
+use state::StateStore;
 
 mod anonaddy;
+mod cli;
+mod config;
+mod daemon;
+mod dashboard;
 mod email_alias;
+mod error;
+mod filter;
 mod hibp;
+mod logging;
+mod metrics;
+mod notify;
+mod redact;
+mod report;
+mod scan;
+mod secret;
+mod server;
+mod shutdown;
+mod state;
 
-fn get_alias_service(client: &reqwest::Client) -> Box<dyn AliasService + '_> {
-    let anonaddy = anonaddy::AnonAddy::new(client);
-    Box::new(anonaddy)
+fn get_alias_service(
+    client: &'static reqwest::Client,
+    token: Option<Secret>,
+    host: Option<String>,
+) -> Result<Box<dyn AliasService>, ConfigError> {
+    let anonaddy = anonaddy::AnonAddy::new(client, token, host)?;
+    Ok(Box::new(anonaddy))
+}
+
+/// Reads the configuration and creates the services from it, reporting every problem found at once.
+async fn create_services(
+    path: Option<&Path>,
+    client: &'static reqwest::Client,
+) -> Result<(Settings, Box<dyn AliasService>, HIBP<'static>), ConfigError> {
+    let config = config::Config::load(path)?;
+    let (credentials, unavailable) = config.read_credentials().await;
+    let settings = config.resolve();
+    let alias_service = get_alias_service(client, credentials.addy_token, credentials.addy_host);
+    let hibp = HIBP::new(client, credentials.hibp_token);
+    if let Some(error) = ConfigError::collect([
+        unavailable,
+        settings.as_ref().err().cloned(),
+        alias_service.as_ref().err().cloned(),
+        hibp.as_ref().err().cloned(),
+    ]) {
+        return Err(error);
+    }
+    Ok((settings?, alias_service?, hibp?))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    configure_logging();
+    let cli = Cli::parse();
+
+    // Log to stdout until the configuration has been read.
+    let logger = log4rs::init_config(logging::build(&logging::LoggingSettings::default())?)?;
 
     let env_file = dotenv();
     if env_file.is_err() {
-        error!("Unable to find .env file");
+        info!("No .env file found.");
     }
-    let client = reqwest::Client::new();
-
-    let alias_service = get_alias_service(&client);
-
-    let hibp = hibp::HIBP::new(&client);
-
-    let aliases = alias_service.get_aliases().await?;
-    for alias in aliases {
-        if alias.is_active() {
-            info!(
-                "Checking breaches for {} - {}",
-                alias.get_email(),
-                alias.get_description().unwrap_or("")
-            );
-            let breaches = hibp.get_breaches(alias.get_email()).await?;
-            if !breaches.is_empty() {
-                debug!("{:#?}", breaches);
-                warn!(
-                    "{} breaches were found for {} - {}",
-                    breaches.len(),
-                    alias.get_email(),
-                    alias.get_description().unwrap_or("")
-                );
-                alias_service.deactivate_alias(alias.get_id()).await?;
+
+    // The client is shared for the lifetime of the process, including with the HTTP server, so leak it to make it
+    // available for the `'static` lifetime.
+    let client: &'static reqwest::Client = Box::leak(Box::new(reqwest::Client::new()));
+
+    let command = cli.command.unwrap_or(Command::Scan);
+    if command.uses_only_state() {
+        let state = StateStore::load(&cli.state_file)?;
+        return run_state_command(command, &state);
+    }
+    let services = create_services(cli.config.as_deref(), client).await;
+    if let Command::Config(ConfigCommand::Validate) = command {
+        if let Err(error) = services {
+            exit_with_error(&error);
+        }
+        println!("Configuration is valid.");
+        return Ok(());
+    }
+    let (settings, alias_service, hibp) = services.unwrap_or_else(|error| exit_with_error(&error));
+
+    let mut logging_settings = settings.logging.clone();
+    if let Some(levels) = cli.log_level {
+        logging_settings.levels = levels;
+    }
+    if let Some(format) = cli.log_format {
+        logging_settings.format = format;
+    }
+    if let Some(path) = cli.log_file {
+        logging_settings.file = Some(match logging_settings.file {
+            Some(file) => logging::FileLog { path, ..file },
+            None => logging::FileLog::new(path),
+        });
+    }
+    logger.set_config(logging::build(&logging_settings)?);
+    redact::set_privacy_mode(cli.privacy || logging_settings.privacy);
+
+    let alias_service: Arc<dyn AliasService> = Arc::from(alias_service);
+
+    let hibp = Arc::new(hibp);
+
+    let state = Arc::new(StateStore::load(&cli.state_file)?);
+
+    let shutdown = Shutdown::listen();
+
+    let scanner = Scanner {
+        client,
+        alias_service: alias_service.as_ref(),
+        hibp: &hibp,
+        state: &state,
+        settings: &settings,
+    };
+
+    match command {
+        Command::Scan => {
+            let report = scanner.scan_and_report(&shutdown).await?;
+            if !report.is_complete() {
+                std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
             }
         }
+        Command::Serve(args) => {
+            let schedule = match (args.cron, args.interval) {
+                (Some(expression), _) => daemon::Schedule::cron(&expression)?,
+                (None, Some(interval)) => daemon::Schedule::Interval(interval),
+                (None, None) => settings
+                    .schedule
+                    .clone()
+                    .unwrap_or(daemon::Schedule::Interval(Duration::from_secs(
+                        24 * 60 * 60,
+                    ))),
+            };
+            if let Some(address) = args.listen {
+                let listener = tokio::net::TcpListener::bind(address).await?;
+                let dashboard = dashboard::Dashboard {
+                    alias_service: alias_service.clone(),
+                    hibp: hibp.clone(),
+                    state: state.clone(),
+                    retry: settings.retry,
+                    form_token: dashboard::generate_form_token()?,
+                    hosts: dashboard::allowed_hosts(address, &settings.dashboard_hosts),
+                };
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = server::serve(listener, dashboard, shutdown).await {
+                        error!("HTTP server failed: {}", error);
+                    }
+                });
+            }
+            daemon::run(&scanner, &schedule, shutdown).await;
+        }
+        Command::Undo(args) => match scanner.undo(args.run).await? {
+            Some(report) => {
+                println!("{}", report.render(settings.output_format));
+                if !report.is_complete() {
+                    std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
+                }
+            }
+            None => println!("There are no deactivations to undo."),
+        },
+        Command::Acknowledge(_) | Command::Config(_) => {
+            unreachable!("Configuration and state commands are handled before scanning")
+        }
     }
     Ok(())
 }
 
-fn configure_logging() {
-    let stdout = ConsoleAppender::builder().build();
+/// Runs a command that only reads or writes the state file, so works without the configuration or the tokens for the
+/// services.
+fn run_state_command(
+    command: Command,
+    state: &StateStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Undo(args) if args.list => {
+            let state = state.snapshot();
+            for run in state.undoable_runs() {
+                println!(
+                    "{} - {} aliases deactivated",
+                    run.to_rfc3339(),
+                    state.undoable_actions(run).len()
+                );
+            }
+        }
+        Command::Acknowledge(args) if args.list => {
+            let state = state.snapshot();
+            let id = args
+                .alias
+                .as_deref()
+                .and_then(|alias| state.find_alias(alias));
+            for (alias_id, alias_state) in &state.aliases {
+                if id.is_none_or(|id| id == alias_id) && !alias_state.acknowledged.is_empty() {
+                    println!(
+                        "{} - {}",
+                        alias_state.email,
+                        alias_state.acknowledged.join(", ")
+                    );
+                }
+            }
+        }
+        Command::Acknowledge(args) => {
+            let alias = args.alias.unwrap_or_default();
+            let id = match state.snapshot().find_alias(&alias) {
+                Some(id) => id.to_string(),
+                None => {
+                    eprintln!("No alias {} has been checked yet.", alias);
+                    std::process::exit(1);
+                }
+            };
+            if args.remove {
+                state.unacknowledge(&id, &args.breaches)?;
+            } else {
+                state.acknowledge(&id, &args.breaches)?;
+            }
+            let acknowledged = state.acknowledged(&id);
+            if acknowledged.is_empty() {
+                println!("No breaches are acknowledged for {}.", alias);
+            } else {
+                println!(
+                    "Breaches acknowledged for {}: {}",
+                    alias,
+                    acknowledged.join(", ")
+                );
+            }
+        }
+        _ => unreachable!("Only commands that use the state alone are run without the services"),
+    }
+    Ok(())
+}
 
-    let config = Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .build(Root::builder().appender("stdout").build(LevelFilter::Info))
-        .unwrap();
+/// The exit code of a scan or undo that finished, but could not check or act on some aliases.
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 2;
 
-    log4rs::init_config(config).unwrap();
+fn exit_with_error(error: &ConfigError) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
 }
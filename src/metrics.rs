@@ -0,0 +1,136 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use prometheus::{
+    register_counter, register_int_counter, register_int_counter_vec, register_int_gauge, Counter,
+    Encoder, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+pub static ALIASES_SCANNED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "has_my_alias_been_pwned_aliases_scanned_total",
+        "Number of aliases checked against haveibeenpwned.com."
+    )
+    .unwrap()
+});
+
+pub static BREACHED_ALIASES: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "has_my_alias_been_pwned_breached_aliases_total",
+        "Number of aliases found in at least one breach."
+    )
+    .unwrap()
+});
+
+pub static DEACTIVATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "has_my_alias_been_pwned_deactivations_total",
+        "Number of attempts to deactivate a breached alias, by result.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub static FAILED_ALIASES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "has_my_alias_been_pwned_failed_aliases_total",
+        "Number of aliases that could not be processed after retrying, by what was being done.",
+        &["stage"]
+    )
+    .unwrap()
+});
+
+pub static HIBP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "has_my_alias_been_pwned_hibp_requests_total",
+        "Number of requests made to haveibeenpwned.com, by response status code.",
+        &["status"]
+    )
+    .unwrap()
+});
+
+pub static HIBP_RATE_LIMIT_WAITS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "has_my_alias_been_pwned_hibp_rate_limit_waits_total",
+        "Number of times haveibeenpwned.com responded with 429 and a wait was needed."
+    )
+    .unwrap()
+});
+
+pub static HIBP_RATE_LIMIT_WAIT_SECONDS: LazyLock<Counter> = LazyLock::new(|| {
+    register_counter!(
+        "has_my_alias_been_pwned_hibp_rate_limit_wait_seconds_total",
+        "Time spent waiting due to haveibeenpwned.com rate limiting."
+    )
+    .unwrap()
+});
+
+pub static LAST_SUCCESSFUL_SCAN: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "has_my_alias_been_pwned_last_successful_scan_timestamp_seconds",
+        "Unix timestamp of the last scan that completed without error."
+    )
+    .unwrap()
+});
+
+/// Records the outcome of an attempt to deactivate an alias.
+pub fn record_deactivation(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    DEACTIVATIONS.with_label_values(&[result]).inc();
+}
+
+/// Records a wait asked for by haveibeenpwned.com rate limiting.
+pub fn record_rate_limit_wait(duration: Duration) {
+    HIBP_RATE_LIMIT_WAITS.inc();
+    HIBP_RATE_LIMIT_WAIT_SECONDS.inc_by(duration.as_secs_f64());
+}
+
+/// Renders all metrics in the Prometheus text exposition format.
+pub fn render() -> String {
+    // Make sure every metric is registered, so they are all exposed before their first use.
+    LazyLock::force(&ALIASES_SCANNED);
+    LazyLock::force(&BREACHED_ALIASES);
+    LazyLock::force(&DEACTIVATIONS);
+    LazyLock::force(&FAILED_ALIASES);
+    LazyLock::force(&HIBP_REQUESTS);
+    LazyLock::force(&HIBP_RATE_LIMIT_WAITS);
+    LazyLock::force(&HIBP_RATE_LIMIT_WAIT_SECONDS);
+    LazyLock::force(&LAST_SUCCESSFUL_SCAN);
+
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_includes_all_metrics() {
+        let rendered = render();
+
+        assert!(rendered.contains("has_my_alias_been_pwned_aliases_scanned_total"));
+        assert!(rendered.contains("has_my_alias_been_pwned_breached_aliases_total"));
+        assert!(rendered.contains("has_my_alias_been_pwned_hibp_rate_limit_waits_total"));
+        assert!(rendered.contains("has_my_alias_been_pwned_hibp_rate_limit_wait_seconds_total"));
+        assert!(rendered.contains("has_my_alias_been_pwned_last_successful_scan_timestamp_seconds"));
+    }
+
+    #[test]
+    fn record_deactivation_labels_by_result() {
+        record_deactivation(true);
+        record_deactivation(false);
+
+        let rendered = render();
+
+        assert!(
+            rendered.contains("has_my_alias_been_pwned_deactivations_total{result=\"success\"}")
+        );
+        assert!(
+            rendered.contains("has_my_alias_been_pwned_deactivations_total{result=\"failure\"}")
+        );
+    }
+}
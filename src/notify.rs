@@ -0,0 +1,144 @@
+use std::fmt::Display;
+
+use log::info;
+
+use crate::redact;
+use crate::report::ScanReport;
+
+/// Somewhere to send the report of a scan that found breached aliases.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notifier {
+    /// POSTs the report as JSON to the URL.
+    Webhook { url: reqwest::Url },
+}
+
+impl Notifier {
+    pub async fn notify(
+        &self,
+        client: &reqwest::Client,
+        report: &ScanReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Notifier::Webhook { url } => {
+                info!("Sending report to webhook {}.", redact::Url(url));
+                let response = client
+                    .post(url.clone())
+                    .json(report)
+                    .send()
+                    .await
+                    .map_err(redact::network_error)?;
+                if !response.status().is_success() {
+                    return Err(Box::new(NotifyError::new(format!(
+                        "Webhook responded with {}.",
+                        response.status()
+                    ))));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotifyError {
+    pub message: String,
+}
+
+impl NotifyError {
+    pub fn new(message: String) -> Self {
+        NotifyError { message }
+    }
+}
+
+impl Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for NotifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httpmock::prelude::*;
+    use serial_test::serial;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn notify_webhook_posts_report() {
+        let server = MockServer::start();
+        let webhook_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hook")
+                .json_body_includes(r#"{"aliases_checked": 1}"#);
+            then.status(204);
+        });
+        let notifier = Notifier::Webhook {
+            url: server.url("/hook").parse().unwrap(),
+        };
+        let report = ScanReport {
+            aliases_checked: 1,
+            ..Default::default()
+        };
+
+        let response = notifier.notify(&reqwest::Client::new(), &report).await;
+
+        assert!(response.is_ok());
+        webhook_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn notify_webhook_returns_error_for_non_success() {
+        let server = MockServer::start();
+        let webhook_mock = server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(500);
+        });
+        let notifier = Notifier::Webhook {
+            url: server.url("/hook").parse().unwrap(),
+        };
+
+        let response = notifier
+            .notify(&reqwest::Client::new(), &ScanReport::default())
+            .await;
+
+        assert!(response.is_err());
+        let error = response.unwrap_err();
+        let actual_error: &NotifyError = match error.downcast_ref::<NotifyError>() {
+            Some(error) => error,
+            None => panic!("Error returned was not a NotifyError!"),
+        };
+        assert_eq!(
+            actual_error.message,
+            "Webhook responded with 500 Internal Server Error."
+        );
+        webhook_mock.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn notify_webhook_hides_url_from_error_in_privacy_mode() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let notifier = Notifier::Webhook {
+            url: format!("http://{}/hook/secret-token", address)
+                .parse()
+                .unwrap(),
+        };
+
+        redact::set_privacy_mode(true);
+        let response = notifier
+            .notify(&reqwest::Client::new(), &ScanReport::default())
+            .await;
+        redact::set_privacy_mode(false);
+
+        let error = response.unwrap_err().to_string();
+        assert!(!error.contains("secret-token"), "{}", error);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether personal details are masked in log output, so logs can be shipped to shared logging infrastructure.
+static PRIVACY_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_privacy_mode(enabled: bool) {
+    PRIVACY_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn privacy_mode() -> bool {
+    PRIVACY_MODE.load(Ordering::Relaxed)
+}
+
+/// Masks the local part of an email address, keeping only its first character, e.g. `j***@example.com`.
+pub fn mask_email(address: &str) -> String {
+    match address.rsplit_once('@') {
+        Some((local_part, domain)) => match local_part.chars().next() {
+            Some(first) => format!("{}***@{}", first, domain),
+            None => format!("***@{}", domain),
+        },
+        None => "***".to_string(),
+    }
+}
+
+/// An email address for logging, masked in privacy mode.
+pub struct Email<'a>(pub &'a str);
+
+impl Display for Email<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if privacy_mode() {
+            write!(f, "{}", mask_email(self.0))
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl Debug for Email<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.to_string(), f)
+    }
+}
+
+/// A URL for logging, with everything after the host hidden in privacy mode, as webhook URLs often carry a token in
+/// their path or query.
+pub struct Url<'a>(pub &'a reqwest::Url);
+
+impl Display for Url<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if privacy_mode() {
+            write!(f, "{}/***", self.0.origin().ascii_serialization())
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// Removes the URL from a request error in privacy mode, for requests that include an email address in the URL.
+pub fn network_error(error: reqwest::Error) -> reqwest::Error {
+    if privacy_mode() {
+        error.without_url()
+    } else {
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    fn mask_email_keeps_first_character_and_domain() {
+        assert_eq!(mask_email("jane.doe@example.com"), "j***@example.com");
+        assert_eq!(mask_email("@example.com"), "***@example.com");
+        assert_eq!(mask_email("not-an-email"), "***");
+    }
+
+    #[test]
+    #[serial]
+    fn email_is_only_masked_in_privacy_mode() {
+        set_privacy_mode(false);
+        assert_eq!(Email("jane@example.com").to_string(), "jane@example.com");
+
+        set_privacy_mode(true);
+        assert_eq!(Email("jane@example.com").to_string(), "j***@example.com");
+        assert_eq!(
+            format!("{:?}", Email("jane@example.com")),
+            "\"j***@example.com\""
+        );
+        set_privacy_mode(false);
+    }
+
+    #[test]
+    #[serial]
+    fn url_hides_path_and_query_in_privacy_mode() {
+        let url =
+            reqwest::Url::parse("https://hooks.example.com/services/secret?token=abc").unwrap();
+
+        set_privacy_mode(true);
+        assert_eq!(Url(&url).to_string(), "https://hooks.example.com/***");
+        set_privacy_mode(false);
+        assert_eq!(
+            Url(&url).to_string(),
+            "https://hooks.example.com/services/secret?token=abc"
+        );
+    }
+}
@@ -0,0 +1,550 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::email_alias::{AccountSummary, AliasInfo};
+use crate::scan::{BreachAction, UnknownAddressAction};
+use crate::state::ActionKind;
+
+/// How the report of each scan is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// What a scan found.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ScanReport {
+    /// The account scanned, if its details could be retrieved.
+    pub account: Option<AccountSummary>,
+    pub aliases_checked: usize,
+    pub breached: Vec<BreachedAlias>,
+    /// Aliases that could not be fully processed, which the rest of the scan carried on without.
+    pub failed: Vec<FailedAlias>,
+    /// Aliases left out of the scan by the include and exclude rules.
+    pub excluded: Vec<ExcludedAlias>,
+    /// Breached addresses on catch-all domains that have no alias.
+    pub unknown_addresses: Vec<UnknownAddress>,
+    /// Catch-all domains that could not be searched or acted on, which the rest of the scan carried on without.
+    pub failed_domains: Vec<FailedDomain>,
+    /// Whether shutdown was requested before every alias and catch-all domain was processed.
+    pub interrupted: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UnknownAddress {
+    pub email: String,
+    pub domain: String,
+    pub breaches: Vec<String>,
+    /// The action taken for the address, if it succeeded.
+    pub action: Option<UnknownAddressAction>,
+}
+
+impl UnknownAddress {
+    fn outcome(&self) -> &'static str {
+        match self.action {
+            Some(UnknownAddressAction::Block) => " (no alias, blocked)",
+            Some(UnknownAddressAction::DisableCatchAll) => " (no alias, catch-all disabled)",
+            Some(UnknownAddressAction::Report) | None => " (no alias)",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExcludedAlias {
+    pub id: String,
+    pub email: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BreachedAlias {
+    /// The alias as it was when checked, written out alongside the breaches rather than nested.
+    #[serde(flatten)]
+    pub alias: AliasInfo,
+    pub breaches: Vec<String>,
+    pub status: AliasStatus,
+    /// The action taken on the alias, if it succeeded and did anything.
+    pub action: Option<BreachAction>,
+    /// The email address of the alias created to replace it.
+    pub replacement: Option<String>,
+}
+
+/// The state of an alias when it was checked. Only active aliases are acted on.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AliasStatus {
+    Active,
+    Inactive,
+    Deleted,
+}
+
+impl BreachedAlias {
+    /// What was done to the alias, as shown after its breaches.
+    fn outcome(&self) -> String {
+        match (self.action, &self.replacement) {
+            _ if self.status == AliasStatus::Inactive => " (inactive)".to_string(),
+            _ if self.status == AliasStatus::Deleted => " (already deleted)".to_string(),
+            (Some(BreachAction::Deactivate), _) => " (deactivated)".to_string(),
+            (Some(BreachAction::Delete), _) => " (deleted)".to_string(),
+            (Some(BreachAction::Tag), _) => " (tagged)".to_string(),
+            (Some(BreachAction::Replace), Some(replacement)) => {
+                format!(" (replaced by {})", replacement)
+            }
+            (_, Some(replacement)) => format!(" (replacement {} created)", replacement),
+            _ => String::new(),
+        }
+    }
+}
+
+/// What was being done to an alias when it failed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Check,
+    Deactivate,
+    Reactivate,
+    Delete,
+    Tag,
+    Replace,
+    /// Creating an inactive alias for a breached address that had none.
+    Block,
+    DisableCatchAll,
+}
+
+impl From<ActionKind> for Stage {
+    fn from(kind: ActionKind) -> Self {
+        match kind {
+            ActionKind::Deactivated => Stage::Deactivate,
+            ActionKind::Reactivated => Stage::Reactivate,
+            ActionKind::Deleted => Stage::Delete,
+            ActionKind::Tagged => Stage::Tag,
+            ActionKind::Replaced => Stage::Replace,
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Check => write!(f, "check"),
+            Stage::Deactivate => write!(f, "deactivate"),
+            Stage::Reactivate => write!(f, "reactivate"),
+            Stage::Delete => write!(f, "delete"),
+            Stage::Tag => write!(f, "tag"),
+            Stage::Replace => write!(f, "replace"),
+            Stage::Block => write!(f, "block"),
+            Stage::DisableCatchAll => write!(f, "disable catch-all"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FailedAlias {
+    pub id: String,
+    pub email: String,
+    pub description: Option<String>,
+    pub stage: Stage,
+    /// The status code of the response, if there was one.
+    pub status: Option<u16>,
+    pub error: String,
+}
+
+/// A catch-all domain that could not be searched or acted on.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FailedDomain {
+    /// The domain, or `None` if the domains could not be listed at all.
+    pub domain: Option<String>,
+    pub stage: Stage,
+    /// The status code of the response, if there was one.
+    pub status: Option<u16>,
+    pub error: String,
+}
+
+/// What undoing the deactivations of a run did.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UndoReport {
+    /// When the run that was undone started.
+    pub run: DateTime<Utc>,
+    /// Email addresses of the aliases reactivated.
+    pub reactivated: Vec<String>,
+    pub failed: Vec<FailedAlias>,
+}
+
+impl UndoReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_string(),
+            OutputFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+}
+
+impl Display for UndoReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Reactivated {} aliases deactivated by the run at {}, {} failed.",
+            self.reactivated.len(),
+            self.run.to_rfc3339(),
+            self.failed.len()
+        )?;
+        for email in &self.reactivated {
+            write!(f, "\n{} (reactivated)", email)?;
+        }
+        for alias in &self.failed {
+            write!(
+                f,
+                "\n{}: failed to {}: {}",
+                alias.email, alias.stage, alias.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ScanReport {
+    /// Whether every alias and catch-all domain was processed without error.
+    pub fn is_complete(&self) -> bool {
+        !self.interrupted && self.failed.is_empty() && self.failed_domains.is_empty()
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_string(),
+            OutputFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+}
+
+impl Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(account) = &self.account {
+            writeln!(f, "{}", account)?;
+        }
+        write!(
+            f,
+            "Checked {} aliases, {} breached, {} failed, {} excluded.",
+            self.aliases_checked,
+            self.breached.len(),
+            self.failed.len(),
+            self.excluded.len()
+        )?;
+        if !self.unknown_addresses.is_empty() {
+            write!(
+                f,
+                " {} breached addresses have no alias.",
+                self.unknown_addresses.len()
+            )?;
+        }
+        if !self.failed_domains.is_empty() {
+            write!(
+                f,
+                " {} catch-all domains failed.",
+                self.failed_domains.len()
+            )?;
+        }
+        if self.interrupted {
+            write!(f, " Stopped early as shutdown was requested.")?;
+        }
+        for breached in &self.breached {
+            write!(
+                f,
+                "\n{} - {}: {}{}",
+                breached.alias.email,
+                breached.alias.description.as_deref().unwrap_or(""),
+                breached.breaches.join(", "),
+                breached.outcome()
+            )?;
+        }
+        for alias in &self.failed {
+            write!(
+                f,
+                "\n{} - {}: failed to {}: {}",
+                alias.email,
+                alias.description.as_deref().unwrap_or(""),
+                alias.stage,
+                alias.error
+            )?;
+        }
+        for alias in &self.excluded {
+            write!(
+                f,
+                "\n{} - {}: excluded",
+                alias.email,
+                alias.description.as_deref().unwrap_or("")
+            )?;
+        }
+        for address in &self.unknown_addresses {
+            write!(
+                f,
+                "\n{}: {}{}",
+                address.email,
+                address.breaches.join(", "),
+                address.outcome()
+            )?;
+        }
+        for domain in &self.failed_domains {
+            write!(
+                f,
+                "\n{}: failed to {}: {}",
+                domain.domain.as_deref().unwrap_or("catch-all domains"),
+                domain.stage,
+                domain.error
+            )?;
+        }
+        let replacements: Vec<_> = self
+            .breached
+            .iter()
+            .filter_map(|breached| Some((&breached.alias.email, breached.replacement.as_ref()?)))
+            .collect();
+        if !replacements.is_empty() {
+            write!(f, "\n\nUpdate these addresses at the breached services:")?;
+            for (old, new) in replacements {
+                write!(f, "\n{} -> {}", old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_alias::fakes::FakeAlias;
+    use crate::email_alias::Alias;
+
+    fn test_report() -> ScanReport {
+        ScanReport {
+            account: None,
+            aliases_checked: 2,
+            breached: vec![BreachedAlias {
+                alias: FakeAlias {
+                    description: Some("description".to_string()),
+                    ..FakeAlias::new("test-id", "email@email.com")
+                }
+                .info(),
+                breaches: vec!["Adobe".to_string(), "BattlefieldHeroes".to_string()],
+                status: AliasStatus::Active,
+                action: Some(BreachAction::Deactivate),
+                replacement: None,
+            }],
+            failed: vec![FailedAlias {
+                id: "failed-id".to_string(),
+                email: "failed@email.com".to_string(),
+                description: None,
+                stage: Stage::Deactivate,
+                status: Some(503),
+                error: "503 - Service unavailable. ".to_string(),
+            }],
+            excluded: vec![ExcludedAlias {
+                id: "excluded-id".to_string(),
+                email: "team@email.com".to_string(),
+                description: Some("Team inbox".to_string()),
+            }],
+            unknown_addresses: vec![],
+            failed_domains: vec![],
+            interrupted: false,
+        }
+    }
+
+    #[test]
+    fn is_complete_returns_false_with_failures() {
+        assert!(!test_report().is_complete());
+        assert!(ScanReport::default().is_complete());
+    }
+
+    #[test]
+    fn is_complete_returns_false_when_interrupted() {
+        let report = ScanReport {
+            interrupted: true,
+            ..Default::default()
+        };
+
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "Checked 0 aliases, 0 breached, 0 failed, 0 excluded. Stopped early as shutdown was requested."
+        );
+    }
+
+    #[test]
+    fn render_text() {
+        let rendered = test_report().render(OutputFormat::Text);
+
+        assert_eq!(
+            rendered,
+            "Checked 2 aliases, 1 breached, 1 failed, 1 excluded.
+email@email.com - description: Adobe, BattlefieldHeroes (deactivated)
+failed@email.com - : failed to deactivate: 503 - Service unavailable. 
+team@email.com - Team inbox: excluded"
+        );
+    }
+
+    #[test]
+    fn render_text_starts_with_account() {
+        let report = ScanReport {
+            account: Some(AccountSummary {
+                provider: "addy.io".to_string(),
+                version: None,
+                username: "johndoe".to_string(),
+                subscription: Some("free".to_string()),
+                alias_count: 12,
+                alias_limit: Some(20),
+                username_count: 1,
+                username_limit: None,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "addy.io account johndoe (free): 12 of 20 aliases, 1 of unlimited usernames.
+Checked 0 aliases, 0 breached, 0 failed, 0 excluded."
+        );
+    }
+
+    #[test]
+    fn render_text_shows_replacement() {
+        let mut report = test_report();
+        report.failed.clear();
+        report.excluded.clear();
+        report.breached[0].action = Some(BreachAction::Replace);
+        report.breached[0].replacement = Some("new@email.com".to_string());
+
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "Checked 2 aliases, 1 breached, 0 failed, 0 excluded.
+email@email.com - description: Adobe, BattlefieldHeroes (replaced by new@email.com)
+
+Update these addresses at the breached services:
+email@email.com -> new@email.com"
+        );
+
+        report.breached[0].status = AliasStatus::Inactive;
+        assert!(report
+            .render(OutputFormat::Text)
+            .contains("Adobe, BattlefieldHeroes (inactive)"));
+
+        report.breached[0].status = AliasStatus::Active;
+        report.breached[0].action = None;
+        assert!(report
+            .render(OutputFormat::Text)
+            .contains("(replacement new@email.com created)"));
+    }
+
+    #[test]
+    fn render_text_lists_unknown_addresses() {
+        let mut report = test_report();
+        report.failed.clear();
+        report.excluded.clear();
+        report.unknown_addresses.push(UnknownAddress {
+            email: "newsletter@example.com".to_string(),
+            domain: "example.com".to_string(),
+            breaches: vec!["Adobe".to_string()],
+            action: None,
+        });
+
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "Checked 2 aliases, 1 breached, 0 failed, 0 excluded. 1 breached addresses have no alias.
+email@email.com - description: Adobe, BattlefieldHeroes (deactivated)
+newsletter@example.com: Adobe (no alias)"
+        );
+
+        let value: serde_json::Value =
+            serde_json::from_str(&report.render(OutputFormat::Json)).unwrap();
+        assert_eq!(value["unknown_addresses"][0]["domain"], "example.com");
+
+        report.unknown_addresses[0].action = Some(UnknownAddressAction::DisableCatchAll);
+        assert!(report
+            .render(OutputFormat::Text)
+            .ends_with("newsletter@example.com: Adobe (no alias, catch-all disabled)"));
+        let value: serde_json::Value =
+            serde_json::from_str(&report.render(OutputFormat::Json)).unwrap();
+        assert_eq!(value["unknown_addresses"][0]["action"], "disable_catch_all");
+    }
+
+    #[test]
+    fn render_text_lists_failed_domains() {
+        let mut report = test_report();
+        report.failed.clear();
+        report.excluded.clear();
+        report.failed_domains = vec![
+            FailedDomain {
+                domain: Some("example.org".to_string()),
+                stage: Stage::Check,
+                status: Some(403),
+                error: "403 - Domain not verified".to_string(),
+            },
+            FailedDomain {
+                domain: None,
+                stage: Stage::Check,
+                status: Some(503),
+                error: "503 - Service unavailable. ".to_string(),
+            },
+        ];
+
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "Checked 2 aliases, 1 breached, 0 failed, 0 excluded. 2 catch-all domains failed.
+email@email.com - description: Adobe, BattlefieldHeroes (deactivated)
+example.org: failed to check: 403 - Domain not verified
+catch-all domains: failed to check: 503 - Service unavailable. "
+        );
+        let value: serde_json::Value =
+            serde_json::from_str(&report.render(OutputFormat::Json)).unwrap();
+        assert_eq!(value["failed_domains"][0]["domain"], "example.org");
+        assert_eq!(
+            value["failed_domains"][1]["domain"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn render_undo_text() {
+        let report = UndoReport {
+            run: "2024-05-01T03:00:00Z".parse().unwrap(),
+            reactivated: vec!["email@email.com".to_string()],
+            failed: vec![FailedAlias {
+                id: "failed-id".to_string(),
+                email: "failed@email.com".to_string(),
+                description: None,
+                stage: Stage::Reactivate,
+                status: Some(404),
+                error: "404 - Not found. ".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "Reactivated 1 aliases deactivated by the run at 2024-05-01T03:00:00+00:00, 1 failed.
+email@email.com (reactivated)
+failed@email.com: failed to reactivate: 404 - Not found. "
+        );
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn render_json() {
+        let rendered = test_report().render(OutputFormat::Json);
+
+        let value: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value["aliases_checked"], 2);
+        assert_eq!(value["breached"][0]["email"], "email@email.com");
+        assert_eq!(value["breached"][0]["domain"], "email.com");
+        assert_eq!(value["breached"][0]["provider"], "Fake");
+        assert_eq!(value["breached"][0]["action"], "deactivate");
+        assert_eq!(value["breached"][0]["status"], "active");
+        assert_eq!(value["failed"][0]["stage"], "deactivate");
+        assert_eq!(value["failed"][0]["status"], 503);
+        assert_eq!(value["excluded"][0]["id"], "excluded-id");
+    }
+}
@@ -0,0 +1,1545 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+
+use crate::config::Settings;
+use crate::email_alias::{
+    AccountSummary, Alias, AliasDomain, AliasQuery, AliasService, DeletedAliases,
+};
+use crate::error::ApiError;
+use crate::hibp::HIBP;
+use crate::metrics;
+use crate::redact;
+use crate::report::{
+    AliasStatus, BreachedAlias, ExcludedAlias, FailedAlias, FailedDomain, ScanReport, Stage,
+    UndoReport, UnknownAddress,
+};
+use crate::shutdown::Shutdown;
+use crate::state::{ActionKind, StateStore};
+
+/// What to do with an alias that has been found in a breach.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BreachAction {
+    Deactivate,
+    Delete,
+    /// Prefix the description with the breaches, e.g. `[PWNED Adobe] Shopping`.
+    Tag,
+    /// Create a new alias to take its place, then deactivate it.
+    Replace,
+    /// Only include the alias in the report.
+    Report,
+}
+
+impl BreachAction {
+    pub const NAMES: &'static str =
+        "\"deactivate\", \"delete\", \"tag\", \"replace\" or \"report\"";
+}
+
+impl FromStr for BreachAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "deactivate" => Ok(BreachAction::Deactivate),
+            "delete" => Ok(BreachAction::Delete),
+            "tag" => Ok(BreachAction::Tag),
+            "replace" => Ok(BreachAction::Replace),
+            "report" => Ok(BreachAction::Report),
+            _ => Err(format!("must be one of {}", BreachAction::NAMES)),
+        }
+    }
+}
+
+impl Display for BreachAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreachAction::Deactivate => write!(f, "deactivate"),
+            BreachAction::Delete => write!(f, "delete"),
+            BreachAction::Tag => write!(f, "tag"),
+            BreachAction::Replace => write!(f, "replace"),
+            BreachAction::Report => write!(f, "report"),
+        }
+    }
+}
+
+/// What to do when a catch-all domain has a breached address with no alias.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownAddressAction {
+    /// Only include the address in the report.
+    Report,
+    /// Create the address as an inactive alias, so mail to it is discarded.
+    Block,
+    /// Turn off catch-all for the domain, so mail to addresses without an alias is rejected.
+    DisableCatchAll,
+}
+
+impl FromStr for UnknownAddressAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "report" => Ok(UnknownAddressAction::Report),
+            "block" => Ok(UnknownAddressAction::Block),
+            "disable_catch_all" => Ok(UnknownAddressAction::DisableCatchAll),
+            _ => Err("must be one of \"report\", \"block\" or \"disable_catch_all\"".to_string()),
+        }
+    }
+}
+
+/// Which action to take for each breached alias.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub action: BreachAction,
+    /// Actions for specific aliases, taking precedence over the default action.
+    pub overrides: Vec<ActionOverride>,
+    pub unknown_addresses: UnknownAddressAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionOverride {
+    /// The ID or email address of the alias.
+    pub alias: String,
+    pub action: BreachAction,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            action: BreachAction::Deactivate,
+            overrides: vec![],
+            unknown_addresses: UnknownAddressAction::Report,
+        }
+    }
+}
+
+impl Policy {
+    pub fn action_for(&self, alias: &dyn Alias) -> BreachAction {
+        self.overrides
+            .iter()
+            .find(|rule| rule.alias == alias.get_id() || rule.alias == alias.get_email())
+            .map_or(self.action, |rule| rule.action)
+    }
+}
+
+/// How requests that fail for a transient reason are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times the request is made in total.
+    pub attempts: u32,
+    /// How long to wait before the first retry, doubling for each retry after that.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs the operation until it succeeds, fails for a reason that is not transient, or runs out of attempts.
+    ///
+    /// When rate limited, the wait asked for by the service is used instead of the delay.
+    pub async fn run<T, F, Fut>(&self, operation: F) -> Result<T, ApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        self.run_recording(operation, |_| {}).await
+    }
+
+    /// Runs a request to haveibeenpwned.com as [`run`](Self::run) does, counting the waits its rate limiting causes
+    /// in the metrics.
+    pub async fn run_hibp<T, F, Fut>(&self, operation: F) -> Result<T, ApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        self.run_recording(operation, metrics::record_rate_limit_wait)
+            .await
+    }
+
+    async fn run_recording<T, F, Fut>(
+        &self,
+        mut operation: F,
+        record_rate_limit_wait: fn(Duration),
+    ) -> Result<T, ApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let mut delay = self.delay;
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(error) if error.is_transient() && attempt < self.attempts => {
+                    let wait = match &error {
+                        ApiError::RateLimited {
+                            retry_after: Some(retry_after),
+                            ..
+                        } => {
+                            record_rate_limit_wait(*retry_after);
+                            *retry_after
+                        }
+                        _ => delay,
+                    };
+                    warn!(
+                        "{} Trying again in {} (attempt {} of {}).",
+                        error,
+                        humantime::format_duration(wait),
+                        attempt + 1,
+                        self.attempts
+                    );
+                    tokio::time::sleep(wait).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Everything needed to check the aliases and act on the results.
+pub struct Scanner<'a> {
+    pub client: &'a reqwest::Client,
+    pub alias_service: &'a dyn AliasService,
+    pub hibp: &'a HIBP<'a>,
+    pub state: &'a StateStore,
+    pub settings: &'a Settings,
+}
+
+impl Scanner<'_> {
+    /// Runs through all active aliases, checking each against haveibeenpwned.com and applying the breach action to
+    /// any that have been breached. The outcome for each alias is recorded in the state store. If inactive aliases
+    /// are checked too, any breached are only reported, as they may still matter for recovering an account.
+    ///
+    /// Transient errors are retried according to the retry policy. An alias that still cannot be checked or acted on
+    /// is recorded in the report as failed and the scan carries on with the rest. Only errors that would affect every
+    /// alias, such as failing to list the aliases or a rejected token, stop the scan.
+    ///
+    /// If shutdown is requested part way through, the alias currently being processed is finished and the report
+    /// covers only the aliases checked so far, marked as interrupted.
+    pub async fn scan(
+        &self,
+        shutdown: &Shutdown,
+    ) -> Result<ScanReport, Box<dyn std::error::Error>> {
+        let retry = self.settings.retry;
+        let run = Utc::now();
+        let mut report = ScanReport::default();
+        match retry.run(|| self.alias_service.get_account_summary()).await {
+            Ok(account) => {
+                info!("{}", account);
+                report.account = Some(account);
+            }
+            Err(error) => warn!("Unable to get the account details: {}", error),
+        }
+        let query = AliasQuery {
+            active: (!self.settings.check_inactive).then_some(true),
+            ..self.settings.query.clone()
+        };
+        let aliases = retry.run(|| self.alias_service.get_aliases(&query)).await?;
+        for alias in aliases {
+            if shutdown.is_requested() {
+                warn!("Shutdown requested, stopping scan early.");
+                report.interrupted = true;
+                return Ok(report);
+            }
+            let status = if alias.is_deleted() {
+                AliasStatus::Deleted
+            } else if alias.is_active() {
+                AliasStatus::Active
+            } else {
+                AliasStatus::Inactive
+            };
+            if status == AliasStatus::Active || self.settings.check_inactive {
+                if !self.settings.filter.allows(alias.as_ref()) {
+                    info!("Skipping {} as it is excluded.", alias);
+                    report.excluded.push(ExcludedAlias {
+                        id: alias.get_id().to_string(),
+                        email: alias.get_email().to_string(),
+                        description: alias.get_description().map(str::to_string),
+                    });
+                    continue;
+                }
+                info!("Checking breaches for {}", alias);
+                metrics::ALIASES_SCANNED.inc();
+                let breaches = match retry
+                    .run_hibp(|| self.hibp.get_breaches(alias.get_email()))
+                    .await
+                {
+                    Ok(breaches) => breaches,
+                    Err(error @ ApiError::Unauthorized { .. }) => return Err(Box::new(error)),
+                    Err(error) => {
+                        report.failed.push(failed(
+                            alias.get_id(),
+                            alias.get_email(),
+                            alias.get_description(),
+                            Stage::Check,
+                            &error,
+                        ));
+                        continue;
+                    }
+                };
+                report.aliases_checked += 1;
+                let found: Vec<String> = breaches
+                    .iter()
+                    .map(|breach| breach.name().to_string())
+                    .collect();
+                if let Err(error) = self.state.record_check(alias.as_ref(), found.clone()) {
+                    report.failed.push(unsaved(
+                        alias.get_id(),
+                        alias.get_email(),
+                        alias.get_description(),
+                        Stage::Check,
+                        error.as_ref(),
+                    ));
+                }
+                let acknowledged = self.state.acknowledged(alias.get_id());
+                let breaches: Vec<_> = breaches
+                    .into_iter()
+                    .filter(|breach| !acknowledged.iter().any(|name| name == breach.name()))
+                    .collect();
+                if breaches.len() < found.len() {
+                    info!(
+                        "{} of the breaches found for {} have been acknowledged.",
+                        found.len() - breaches.len(),
+                        alias
+                    );
+                }
+                let breach_names: Vec<String> = breaches
+                    .iter()
+                    .map(|breach| breach.name().to_string())
+                    .collect();
+                if !breaches.is_empty() {
+                    debug!("{:#?}", breaches);
+                    warn!("{} breaches were found for {}", breaches.len(), alias);
+                    metrics::BREACHED_ALIASES.inc();
+                    let mut breached_alias = BreachedAlias {
+                        alias: alias.info(),
+                        breaches: breach_names,
+                        status,
+                        action: None,
+                        replacement: None,
+                    };
+                    let action = match status {
+                        AliasStatus::Active => self.settings.policy.action_for(alias.as_ref()),
+                        AliasStatus::Inactive | AliasStatus::Deleted => BreachAction::Report,
+                    };
+                    match self
+                        .apply(
+                            alias.as_ref(),
+                            action,
+                            &mut breached_alias,
+                            &mut report.account,
+                        )
+                        .await
+                    {
+                        Ok(Some(kind)) => {
+                            breached_alias.action = Some(action);
+                            if let Err(error) = self.state.record_action(alias.get_id(), run, kind)
+                            {
+                                report.failed.push(unsaved(
+                                    alias.get_id(),
+                                    alias.get_email(),
+                                    alias.get_description(),
+                                    kind.into(),
+                                    error.as_ref(),
+                                ));
+                            }
+                        }
+                        Ok(None) => {}
+                        Err((stage, error)) => report.failed.push(failed(
+                            alias.get_id(),
+                            alias.get_email(),
+                            alias.get_description(),
+                            stage,
+                            &error,
+                        )),
+                    }
+                    report.breached.push(breached_alias);
+                }
+            }
+        }
+        if self.settings.domain_search && !self.search_domains(&mut report, shutdown).await? {
+            report.interrupted = true;
+            return Ok(report);
+        }
+        if report.is_complete() {
+            metrics::LAST_SUCCESSFUL_SCAN.set(Utc::now().timestamp());
+        } else {
+            warn!(
+                "Scan finished, but {} aliases could not be processed.",
+                report.failed.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// Applies the action to the breached alias, returning what was done to it, if anything. A replacement is added to
+    /// the report as soon as it is created, so it is not lost if deactivating the old alias then fails.
+    ///
+    /// Replacements are only created while the account, if known, has room for another alias. As the old alias is
+    /// deactivated, a replacement only adds to the account's alias count if that fails.
+    async fn apply(
+        &self,
+        alias: &dyn Alias,
+        action: BreachAction,
+        breached_alias: &mut BreachedAlias,
+        account: &mut Option<AccountSummary>,
+    ) -> Result<Option<ActionKind>, (Stage, ApiError)> {
+        let retry = self.settings.retry;
+        let id = alias.get_id();
+        match action {
+            BreachAction::Deactivate => {
+                let deactivated = retry.run(|| self.alias_service.deactivate_alias(id)).await;
+                metrics::record_deactivation(deactivated.is_ok());
+                deactivated.map_err(|error| (Stage::Deactivate, error))?;
+                Ok(Some(ActionKind::Deactivated))
+            }
+            BreachAction::Delete => {
+                retry
+                    .run(|| self.alias_service.delete_alias(id))
+                    .await
+                    .map_err(|error| (Stage::Delete, error))?;
+                Ok(Some(ActionKind::Deleted))
+            }
+            BreachAction::Tag => {
+                let description =
+                    tagged_description(alias.get_description(), &breached_alias.breaches);
+                if alias.get_description() != Some(description.as_str()) {
+                    retry
+                        .run(|| self.alias_service.set_description(id, &description))
+                        .await
+                        .map_err(|error| (Stage::Tag, error))?;
+                }
+                Ok(Some(ActionKind::Tagged))
+            }
+            BreachAction::Replace => {
+                if let Some(account) = account.as_ref() {
+                    if !account.has_room_for_alias() {
+                        let limit = account.alias_limit.unwrap_or_default();
+                        return Err((Stage::Replace, ApiError::LimitReached { limit }));
+                    }
+                }
+                // Not retried, as a request that failed after the service created the alias would create a second one.
+                let replacement = self
+                    .alias_service
+                    .create_replacement(alias)
+                    .await
+                    .map_err(|error| (Stage::Replace, error))?;
+                info!("Created {} to replace {}.", replacement, alias);
+                breached_alias.replacement = Some(replacement.get_email().to_string());
+                let deactivated = retry.run(|| self.alias_service.deactivate_alias(id)).await;
+                metrics::record_deactivation(deactivated.is_ok());
+                if let (Err(_), Some(account)) = (&deactivated, account.as_mut()) {
+                    account.alias_count += 1;
+                }
+                deactivated.map_err(|error| (Stage::Replace, error))?;
+                Ok(Some(ActionKind::Replaced))
+            }
+            BreachAction::Report => Ok(None),
+        }
+    }
+
+    /// Blocks an address on a catch-all domain by creating an alias for it and deactivating it, returning whether it
+    /// was blocked.
+    ///
+    /// Only the deactivation is retried, as retrying the creation could create the alias twice. If the deactivation
+    /// still fails, the alias is reported as created but still active.
+    async fn block_address(
+        &self,
+        domain: &AliasDomain,
+        local_part: &str,
+        email: &str,
+        report: &mut ScanReport,
+    ) -> bool {
+        let alias = match self
+            .alias_service
+            .create_blocked_alias(domain, local_part)
+            .await
+        {
+            Ok(alias) => alias,
+            Err(error) => {
+                report
+                    .failed
+                    .push(failed("", email, None, Stage::Block, &error));
+                return false;
+            }
+        };
+        let deactivated = self
+            .settings
+            .retry
+            .run(|| self.alias_service.deactivate_alias(alias.get_id()))
+            .await;
+        match deactivated {
+            Ok(()) => {
+                info!("Created {} as an inactive alias to block it.", alias);
+                true
+            }
+            Err(error) => {
+                report.failed.push(failed(
+                    alias.get_id(),
+                    alias.get_email(),
+                    Some("created to block the address, but still active"),
+                    Stage::Deactivate,
+                    &error,
+                ));
+                false
+            }
+        }
+    }
+
+    /// Searches the active catch-all domains for breached addresses that have no alias, as mail to them would create
+    /// one that the scan has never seen.
+    ///
+    /// Returns `false` if shutdown was requested before the search finished. The domain being searched is finished
+    /// first.
+    async fn search_domains(
+        &self,
+        report: &mut ScanReport,
+        shutdown: &Shutdown,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let retry = self.settings.retry;
+        let domains = match retry.run(|| self.alias_service.get_domains()).await {
+            Ok(domains) => domains,
+            Err(error) => {
+                report
+                    .failed_domains
+                    .push(failed_domain(None, Stage::Check, &error));
+                return Ok(true);
+            }
+        };
+        let query = AliasQuery {
+            deleted: DeletedAliases::Include,
+            ..Default::default()
+        };
+        let known: HashSet<String> =
+            match retry.run(|| self.alias_service.get_aliases(&query)).await {
+                Ok(aliases) => aliases
+                    .iter()
+                    .map(|alias| alias.get_email().to_lowercase())
+                    .collect(),
+                Err(error) => {
+                    report
+                        .failed_domains
+                        .push(failed_domain(None, Stage::Check, &error));
+                    return Ok(true);
+                }
+            };
+        for domain in domains
+            .iter()
+            .filter(|domain| domain.active && domain.catch_all)
+        {
+            if shutdown.is_requested() {
+                warn!("Shutdown requested, stopping scan early.");
+                return Ok(false);
+            }
+            info!("Searching {} for breached addresses", domain.domain);
+            let addresses = match retry
+                .run_hibp(|| self.hibp.search_domain(&domain.domain))
+                .await
+            {
+                Ok(addresses) => addresses,
+                Err(error) => {
+                    report
+                        .failed_domains
+                        .push(failed_domain(Some(domain), Stage::Check, &error));
+                    continue;
+                }
+            };
+            let mut unknown: Vec<UnknownAddress> = vec![];
+            for (local_part, breaches) in addresses {
+                let email = format!("{}@{}", local_part, domain.domain);
+                if known.contains(&email.to_lowercase()) {
+                    continue;
+                }
+                warn!(
+                    "{} breaches were found for {}, which has no alias",
+                    breaches.len(),
+                    redact::Email(&email)
+                );
+                let mut address = UnknownAddress {
+                    email,
+                    domain: domain.domain.clone(),
+                    breaches,
+                    action: None,
+                };
+                if self.settings.policy.unknown_addresses == UnknownAddressAction::Block
+                    && self
+                        .block_address(domain, &local_part, &address.email, report)
+                        .await
+                {
+                    address.action = Some(UnknownAddressAction::Block);
+                }
+                unknown.push(address);
+            }
+            if !unknown.is_empty()
+                && self.settings.policy.unknown_addresses == UnknownAddressAction::DisableCatchAll
+            {
+                match retry
+                    .run(|| self.alias_service.disable_catch_all(domain))
+                    .await
+                {
+                    Ok(()) => {
+                        info!("Disabled catch-all for {}.", domain.domain);
+                        for address in &mut unknown {
+                            address.action = Some(UnknownAddressAction::DisableCatchAll);
+                        }
+                    }
+                    Err(error) => report.failed_domains.push(failed_domain(
+                        Some(domain),
+                        Stage::DisableCatchAll,
+                        &error,
+                    )),
+                }
+            }
+            report.unknown_addresses.extend(unknown);
+        }
+        Ok(true)
+    }
+
+    /// Scans the aliases, then writes out the report and sends it to the notifiers if anything was breached or
+    /// failed.
+    pub async fn scan_and_report(
+        &self,
+        shutdown: &Shutdown,
+    ) -> Result<ScanReport, Box<dyn std::error::Error>> {
+        let report = self.scan(shutdown).await?;
+        println!("{}", report.render(self.settings.output_format));
+        if !report.breached.is_empty()
+            || !report.unknown_addresses.is_empty()
+            || !report.is_complete()
+        {
+            for notifier in &self.settings.notifiers {
+                if let Err(error) = notifier.notify(self.client, &report).await {
+                    error!("Failed to send notification: {}", error);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Reactivates the aliases deactivated by a run, for when a breach turns out to be a false positive. If no run is
+    /// given, the most recent one with deactivations still to be undone is used.
+    ///
+    /// Returns `None` if there is nothing to undo.
+    pub async fn undo(
+        &self,
+        run: Option<DateTime<Utc>>,
+    ) -> Result<Option<UndoReport>, Box<dyn std::error::Error>> {
+        let state = self.state.snapshot();
+        let run = match run.or_else(|| state.undoable_runs().last().copied()) {
+            Some(run) => run,
+            None => return Ok(None),
+        };
+        let now = Utc::now();
+        let mut report = UndoReport {
+            run,
+            reactivated: vec![],
+            failed: vec![],
+        };
+        for action in state.undoable_actions(run) {
+            let description = state
+                .aliases
+                .get(&action.alias_id)
+                .and_then(|alias_state| alias_state.description.as_deref());
+            let reactivated = self
+                .settings
+                .retry
+                .run(|| self.alias_service.reactivate_alias(&action.alias_id))
+                .await;
+            match reactivated {
+                Ok(()) => {
+                    info!("Reactivated {}.", redact::Email(&action.email));
+                    report.reactivated.push(action.email.clone());
+                    if let Err(error) = self.state.record_reactivation(&action.alias_id, now) {
+                        report.failed.push(unsaved(
+                            &action.alias_id,
+                            &action.email,
+                            description,
+                            Stage::Reactivate,
+                            error.as_ref(),
+                        ));
+                    }
+                }
+                Err(error) => report.failed.push(failed(
+                    &action.alias_id,
+                    &action.email,
+                    description,
+                    Stage::Reactivate,
+                    &error,
+                )),
+            }
+        }
+        Ok(Some(report))
+    }
+}
+
+/// Prefixes the description with the names of the breaches, replacing the prefix from any earlier tag.
+fn tagged_description(description: Option<&str>, breaches: &[String]) -> String {
+    let description = description.unwrap_or("");
+    let untagged = match description.strip_prefix("[PWNED ") {
+        Some(tagged) => match tagged.split_once("] ") {
+            Some((_, rest)) => rest,
+            None if tagged.ends_with(']') => "",
+            None => description,
+        },
+        None => description,
+    };
+    let tag = format!("[PWNED {}]", breaches.join(", "));
+    if untagged.is_empty() {
+        tag
+    } else {
+        format!("{} {}", tag, untagged)
+    }
+}
+
+/// Describes an alias whose outcome could not be saved to the state, logging the error.
+fn unsaved(
+    id: &str,
+    email: &str,
+    description: Option<&str>,
+    stage: Stage,
+    error: &dyn std::error::Error,
+) -> FailedAlias {
+    error!(
+        "Failed to save the state after {} {} - {}: {}",
+        stage,
+        redact::Email(email),
+        description.unwrap_or(""),
+        error
+    );
+    metrics::FAILED_ALIASES
+        .with_label_values(&[&stage.to_string()])
+        .inc();
+    FailedAlias {
+        id: id.to_string(),
+        email: email.to_string(),
+        description: description.map(str::to_string),
+        stage,
+        status: None,
+        error: format!("Could not save the state: {}", error),
+    }
+}
+
+fn failed_domain(domain: Option<&AliasDomain>, stage: Stage, error: &ApiError) -> FailedDomain {
+    let domain = domain.map(|domain| domain.domain.clone());
+    error!(
+        "Failed to {} {}: {}",
+        stage,
+        domain.as_deref().unwrap_or("catch-all domains"),
+        error
+    );
+    FailedDomain {
+        domain,
+        stage,
+        status: error.status(),
+        error: error.to_string(),
+    }
+}
+
+/// Logs the error and describes the failure for the report.
+fn failed(
+    id: &str,
+    email: &str,
+    description: Option<&str>,
+    stage: Stage,
+    error: &ApiError,
+) -> FailedAlias {
+    error!(
+        "Failed to {} {} - {}: {}",
+        stage,
+        redact::Email(email),
+        description.unwrap_or(""),
+        error
+    );
+    metrics::FAILED_ALIASES
+        .with_label_values(&[&stage.to_string()])
+        .inc();
+    FailedAlias {
+        id: id.to_string(),
+        email: email.to_string(),
+        description: description.map(str::to_string),
+        stage,
+        status: error.status(),
+        error: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use httpmock::prelude::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::email_alias::fakes::{FakeAlias, FakeAliasService};
+    use crate::email_alias::DomainKind;
+    use crate::filter::AliasRule;
+
+    const BREACH: &str = r#"[{"Name":"Adobe","Title":"Adobe","Domain":"adobe.com","BreachDate":"2013-10-04","AddedDate":"2013-12-04T00:00:00Z","ModifiedDate":"2022-05-15T23:52:49Z","PwnCount":152445165,"Description":"","LogoPath":"","DataClasses":["Email addresses"],"IsVerified":true,"IsFabricated":false,"IsSensitive":false,"IsRetired":false,"IsSpamList":false}]"#;
+
+    fn test_settings() -> Settings {
+        let mut settings = Config::default().resolve().unwrap();
+        settings.retry.delay = Duration::from_millis(1);
+        settings
+    }
+
+    /// Everything a scanner needs, with HIBP served by the mock server and the state kept in a temporary directory.
+    struct TestScan {
+        client: &'static reqwest::Client,
+        alias_service: FakeAliasService,
+        hibp: HIBP<'static>,
+        state: StateStore,
+        settings: Settings,
+        directory: TempDir,
+    }
+
+    impl TestScan {
+        fn new(server: &MockServer, alias_service: FakeAliasService, settings: Settings) -> Self {
+            let client: &'static reqwest::Client = Box::leak(Box::new(reqwest::Client::new()));
+            let directory = tempdir().unwrap();
+            TestScan {
+                client,
+                alias_service,
+                hibp: HIBP::with_host(client, &server.url("")),
+                state: StateStore::load(&directory.path().join("state.json")).unwrap(),
+                settings,
+                directory,
+            }
+        }
+
+        fn scanner(&self) -> Scanner<'_> {
+            Scanner {
+                client: self.client,
+                alias_service: &self.alias_service,
+                hibp: &self.hibp,
+                state: &self.state,
+                settings: &self.settings,
+            }
+        }
+
+        /// Scans until finished, without shutdown being requested.
+        async fn scan(&self) -> ScanReport {
+            let (_sender, shutdown) = Shutdown::channel();
+            self.scanner().scan(&shutdown).await.unwrap()
+        }
+    }
+
+    fn mock_breaches(server: &MockServer, email: &str, status: u16, body: &str) {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/api/v3/breachedaccount/{}", email));
+            then.status(status).body(body);
+        });
+    }
+
+    #[tokio::test]
+    async fn run_retries_transient_errors() {
+        let attempts = AtomicU32::new(0);
+        let policy = RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+        };
+
+        let result = policy
+            .run(|| async {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(ApiError::Unavailable {
+                        status: 503,
+                        body: String::new(),
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn run_hibp_records_rate_limit_waits() {
+        let policy = RetryPolicy {
+            attempts: 2,
+            delay: Duration::from_secs(60),
+        };
+        let rate_limited_once = |attempts: &AtomicU32| {
+            let first = attempts.fetch_add(1, Ordering::SeqCst) == 0;
+            async move {
+                if first {
+                    Err(ApiError::RateLimited {
+                        status: 429,
+                        body: String::new(),
+                        retry_after: Some(Duration::from_millis(1)),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+        };
+
+        let waits = metrics::HIBP_RATE_LIMIT_WAITS.get();
+        let attempts = AtomicU32::new(0);
+        let result = policy.run(|| rate_limited_once(&attempts)).await;
+        assert!(result.is_ok());
+        assert_eq!(metrics::HIBP_RATE_LIMIT_WAITS.get(), waits);
+
+        let attempts = AtomicU32::new(0);
+        let result = policy.run_hibp(|| rate_limited_once(&attempts)).await;
+        assert!(result.is_ok());
+        assert_eq!(metrics::HIBP_RATE_LIMIT_WAITS.get(), waits + 1);
+    }
+
+    #[tokio::test]
+    async fn run_does_not_retry_other_errors() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), ApiError> = RetryPolicy::default()
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ApiError::NotFound {
+                    status: 404,
+                    body: String::new(),
+                })
+            })
+            .await;
+
+        assert!(matches!(result, Err(ApiError::NotFound { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn scan_continues_when_state_cannot_be_saved() {
+        let server = MockServer::start();
+        mock_breaches(&server, "first@email.com", 200, BREACH);
+        mock_breaches(&server, "second@email.com", 404, "");
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("first", "first@email.com"),
+            FakeAlias::new("second", "second@email.com"),
+        ]);
+        let mut test = TestScan::new(&server, alias_service, test_settings());
+        let missing = test.directory.path().join("missing").join("state.json");
+        test.state = StateStore::load(&missing).unwrap();
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 2);
+        assert_eq!(report.breached.len(), 1);
+        assert_eq!(report.breached[0].alias.id, "first");
+        assert_eq!(report.breached[0].action, Some(BreachAction::Deactivate));
+        let failed: Vec<_> = report
+            .failed
+            .iter()
+            .map(|failed| (failed.id.as_str(), failed.stage))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("first", Stage::Check),
+                ("first", Stage::Deactivate),
+                ("second", Stage::Check)
+            ]
+        );
+        assert_eq!(report.failed[0].status, None);
+        assert!(report.failed[0]
+            .error
+            .starts_with("Could not save the state: "));
+        assert_eq!(
+            *test.alias_service.deactivated.lock().unwrap(),
+            vec!["first".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_continues_after_alias_fails() {
+        let server = MockServer::start();
+        mock_breaches(&server, "broken@email.com", 400, "Bad request");
+        mock_breaches(&server, "breached@email.com", 200, BREACH);
+        mock_breaches(&server, "failing@email.com", 200, BREACH);
+        let mut alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("broken", "broken@email.com"),
+            FakeAlias::new("breached", "breached@email.com"),
+            FakeAlias::new("failing", "failing@email.com"),
+        ]);
+        alias_service.failing = vec!["failing".to_string()];
+        let test = TestScan::new(&server, alias_service, test_settings());
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 2);
+        assert_eq!(report.breached.len(), 2);
+        assert_eq!(report.breached[0].action, Some(BreachAction::Deactivate));
+        assert_eq!(report.breached[1].action, None);
+        assert_eq!(report.failed.len(), 2);
+        assert_eq!(report.failed[0].id, "broken");
+        assert_eq!(report.failed[0].stage, Stage::Check);
+        assert_eq!(report.failed[0].status, Some(400));
+        assert_eq!(report.failed[1].id, "failing");
+        assert_eq!(report.failed[1].stage, Stage::Deactivate);
+        assert!(!report.is_complete());
+        assert_eq!(
+            *test.alias_service.deactivation_attempts.lock().unwrap(),
+            vec!["breached", "failing", "failing", "failing"]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_continues_after_forbidden() {
+        let server = MockServer::start();
+        mock_breaches(&server, "refused@email.com", 403, "Forbidden");
+        mock_breaches(&server, "breached@email.com", 200, BREACH);
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("refused", "refused@email.com"),
+            FakeAlias::new("breached", "breached@email.com"),
+        ]);
+        let test = TestScan::new(&server, alias_service, test_settings());
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 1);
+        assert_eq!(report.breached.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].id, "refused");
+        assert_eq!(report.failed[0].status, Some(403));
+    }
+
+    #[tokio::test]
+    async fn scan_applies_action_for_each_alias() {
+        let server = MockServer::start();
+        for email in ["tagged", "deleted", "replaced", "reported"] {
+            mock_breaches(&server, &format!("{}@email.com", email), 200, BREACH);
+        }
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias {
+                description: Some("[PWNED LinkedIn] Shopping".to_string()),
+                ..FakeAlias::new("tagged", "tagged@email.com")
+            },
+            FakeAlias::new("deleted", "deleted@email.com"),
+            FakeAlias::new("replaced", "replaced@email.com"),
+            FakeAlias::new("reported", "reported@email.com"),
+        ]);
+        let mut settings = test_settings();
+        settings.policy = Policy {
+            action: BreachAction::Tag,
+            overrides: vec![
+                ActionOverride {
+                    alias: "deleted".to_string(),
+                    action: BreachAction::Delete,
+                },
+                ActionOverride {
+                    alias: "replaced@email.com".to_string(),
+                    action: BreachAction::Replace,
+                },
+                ActionOverride {
+                    alias: "reported".to_string(),
+                    action: BreachAction::Report,
+                },
+            ],
+            ..Policy::default()
+        };
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert!(report.is_complete());
+        let actions: Vec<_> = report.breached.iter().map(|alias| alias.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                Some(BreachAction::Tag),
+                Some(BreachAction::Delete),
+                Some(BreachAction::Replace),
+                None
+            ]
+        );
+        assert_eq!(
+            report.breached[2].replacement.as_deref(),
+            Some("replacement@email.com")
+        );
+        assert_eq!(
+            *test.alias_service.descriptions.lock().unwrap(),
+            vec![("tagged".to_string(), "[PWNED Adobe] Shopping".to_string())]
+        );
+        assert_eq!(*test.alias_service.deleted.lock().unwrap(), vec!["deleted"]);
+        assert_eq!(
+            *test.alias_service.deactivated.lock().unwrap(),
+            vec!["replaced"]
+        );
+        let undoable = test.state.snapshot();
+        let run = undoable.undoable_runs()[0];
+        assert_eq!(undoable.undoable_actions(run)[0].alias_id, "replaced");
+    }
+
+    #[test]
+    fn tagged_description_replaces_earlier_tag() {
+        let breaches = vec!["Adobe".to_string(), "LinkedIn".to_string()];
+
+        assert_eq!(
+            tagged_description(None, &breaches),
+            "[PWNED Adobe, LinkedIn]"
+        );
+        assert_eq!(
+            tagged_description(Some("Shopping"), &breaches),
+            "[PWNED Adobe, LinkedIn] Shopping"
+        );
+        assert_eq!(
+            tagged_description(Some("[PWNED Adobe] Shopping"), &breaches),
+            "[PWNED Adobe, LinkedIn] Shopping"
+        );
+        assert_eq!(
+            tagged_description(Some("[PWNED Adobe]"), &breaches),
+            "[PWNED Adobe, LinkedIn]"
+        );
+    }
+
+    #[test]
+    fn tagged_description_keeps_description_without_closing_tag() {
+        let breaches = vec!["Adobe".to_string()];
+
+        assert_eq!(
+            tagged_description(Some("[PWNED notes"), &breaches),
+            "[PWNED Adobe] [PWNED notes"
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_does_not_replace_aliases_over_account_limit() {
+        let server = MockServer::start();
+        mock_breaches(&server, "first@email.com", 200, BREACH);
+        mock_breaches(&server, "second@email.com", 200, BREACH);
+        let mut alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("first", "first@email.com"),
+            FakeAlias::new("second", "second@email.com"),
+        ]);
+        alias_service.account = Some(AccountSummary {
+            provider: "Fake".to_string(),
+            version: None,
+            username: "johndoe".to_string(),
+            subscription: None,
+            alias_count: 20,
+            alias_limit: Some(20),
+            username_count: 0,
+            username_limit: None,
+        });
+        let mut settings = test_settings();
+        settings.policy.action = BreachAction::Replace;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(report.account.unwrap().alias_count, 20);
+        assert_eq!(report.failed.len(), 2);
+        assert_eq!(report.failed[0].stage, Stage::Replace);
+        assert_eq!(report.failed[0].status, None);
+        assert_eq!(
+            report.failed[1].error,
+            "The account has reached its limit of 20 aliases."
+        );
+        assert!(test.alias_service.replacements.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scan_does_not_retry_creating_replacement() {
+        let server = MockServer::start();
+        mock_breaches(&server, "failing@email.com", 200, BREACH);
+        let mut alias_service =
+            FakeAliasService::new(vec![FakeAlias::new("failing", "failing@email.com")]);
+        alias_service.failing = vec!["failing".to_string()];
+        let mut settings = test_settings();
+        settings.policy.action = BreachAction::Replace;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].stage, Stage::Replace);
+        assert_eq!(
+            *test.alias_service.replacement_attempts.lock().unwrap(),
+            vec!["failing"]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_only_lists_active_aliases_matching_query() {
+        let server = MockServer::start();
+        let breaches_mock = server.mock(|when, then| {
+            when.method(GET);
+            then.status(404);
+        });
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("listed", "listed@johndoe.anonaddy.com"),
+            FakeAlias {
+                active: false,
+                ..FakeAlias::new("inactive", "inactive@johndoe.anonaddy.com")
+            },
+            FakeAlias::new("other-domain", "other@example.com"),
+        ]);
+        let mut settings = test_settings();
+        settings.query.domains = vec!["johndoe.anonaddy.com".to_string()];
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 1);
+        breaches_mock.assert_calls(1);
+        assert!(test.state.snapshot().aliases.contains_key("listed"));
+    }
+
+    #[tokio::test]
+    async fn scan_only_reports_inactive_and_deleted_aliases() {
+        let server = MockServer::start();
+        mock_breaches(&server, "inactive@email.com", 200, BREACH);
+        mock_breaches(&server, "deleted@email.com", 200, BREACH);
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias {
+                active: false,
+                ..FakeAlias::new("inactive", "inactive@email.com")
+            },
+            FakeAlias {
+                active: false,
+                deleted: true,
+                ..FakeAlias::new("deleted", "deleted@email.com")
+            },
+        ]);
+        let mut settings = test_settings();
+        settings.check_inactive = true;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 2);
+        let statuses: Vec<_> = report.breached.iter().map(|alias| alias.status).collect();
+        assert_eq!(statuses, vec![AliasStatus::Inactive, AliasStatus::Deleted]);
+        assert!(report.breached.iter().all(|alias| alias.action.is_none()));
+        assert!(test
+            .alias_service
+            .deactivation_attempts
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn scan_skips_excluded_aliases() {
+        let server = MockServer::start();
+        let breaches_mock = server.mock(|when, then| {
+            when.method(GET);
+            then.status(200).body(BREACH);
+        });
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias {
+                description: Some("Team inbox".to_string()),
+                ..FakeAlias::new("team", "team@email.com")
+            },
+            FakeAlias::new("personal", "personal@email.com"),
+        ]);
+        let mut settings = test_settings();
+        settings.filter.exclude = vec![AliasRule {
+            description: Some("team".to_string()),
+            ..Default::default()
+        }];
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 1);
+        assert_eq!(report.excluded.len(), 1);
+        assert_eq!(report.excluded[0].id, "team");
+        assert_eq!(
+            *test.alias_service.deactivated.lock().unwrap(),
+            vec!["personal"]
+        );
+        breaches_mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn scan_ignores_acknowledged_breaches() {
+        let server = MockServer::start();
+        mock_breaches(&server, "acknowledged@email.com", 200, BREACH);
+        let alias = FakeAlias::new("acknowledged", "acknowledged@email.com");
+        let alias_service = FakeAliasService::new(vec![alias.clone()]);
+        let test = TestScan::new(&server, alias_service, test_settings());
+        test.state.record_check(&alias, vec![]).unwrap();
+        test.state
+            .acknowledge("acknowledged", &["Adobe".to_string()])
+            .unwrap();
+
+        let report = test.scan().await;
+
+        assert_eq!(report.aliases_checked, 1);
+        assert!(report.breached.is_empty());
+        assert!(test.alias_service.deactivated.lock().unwrap().is_empty());
+        assert_eq!(
+            test.state.snapshot().aliases["acknowledged"].breaches,
+            vec!["Adobe"]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_reports_breached_addresses_without_alias() {
+        let server = MockServer::start();
+        mock_breaches(&server, "known@example.com", 404, "");
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v3/breacheddomain/example.com");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"known": ["Adobe"], "Unknown": ["Adobe", "LinkedIn"]}"#);
+        });
+        let verified_mock = server.mock(|when, then| {
+            when.method(GET).path("/api/v3/breacheddomain/example.org");
+            then.status(403).body("Domain not verified");
+        });
+        let mut alias_service =
+            FakeAliasService::new(vec![FakeAlias::new("known", "known@example.com")]);
+        alias_service.domains = vec![
+            AliasDomain {
+                id: "example-com".to_string(),
+                domain: "example.com".to_string(),
+                kind: DomainKind::Custom,
+                active: true,
+                catch_all: true,
+            },
+            AliasDomain {
+                id: "example-org".to_string(),
+                domain: "example.org".to_string(),
+                kind: DomainKind::Custom,
+                active: true,
+                catch_all: true,
+            },
+            AliasDomain {
+                id: "example-net".to_string(),
+                domain: "example.net".to_string(),
+                kind: DomainKind::Custom,
+                active: true,
+                catch_all: false,
+            },
+        ];
+        let mut settings = test_settings();
+        settings.domain_search = true;
+        settings.policy.unknown_addresses = UnknownAddressAction::Block;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(
+            report.unknown_addresses,
+            vec![UnknownAddress {
+                email: "Unknown@example.com".to_string(),
+                domain: "example.com".to_string(),
+                breaches: vec!["Adobe".to_string(), "LinkedIn".to_string()],
+                action: Some(UnknownAddressAction::Block),
+            }]
+        );
+        assert_eq!(
+            *test.alias_service.blocked.lock().unwrap(),
+            vec!["Unknown@example.com"]
+        );
+        assert_eq!(
+            *test.alias_service.deactivated.lock().unwrap(),
+            vec!["Unknown@example.com-blocked"]
+        );
+        assert!(report.failed.is_empty());
+        assert_eq!(report.failed_domains.len(), 1);
+        assert_eq!(
+            report.failed_domains[0].domain.as_deref(),
+            Some("example.org")
+        );
+        assert_eq!(report.failed_domains[0].status, Some(403));
+        verified_mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn scan_reports_blocked_address_left_active() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v3/breacheddomain/example.com");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"unknown": ["Adobe"]}"#);
+        });
+        let mut alias_service = FakeAliasService::new(vec![]);
+        alias_service.domains = vec![AliasDomain {
+            id: "example-com".to_string(),
+            domain: "example.com".to_string(),
+            kind: DomainKind::Custom,
+            active: true,
+            catch_all: true,
+        }];
+        alias_service.failing = vec!["unknown@example.com-blocked".to_string()];
+        let mut settings = test_settings();
+        settings.domain_search = true;
+        settings.policy.unknown_addresses = UnknownAddressAction::Block;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(
+            *test.alias_service.blocked.lock().unwrap(),
+            vec!["unknown@example.com"]
+        );
+        assert_eq!(
+            *test.alias_service.deactivation_attempts.lock().unwrap(),
+            vec![
+                "unknown@example.com-blocked",
+                "unknown@example.com-blocked",
+                "unknown@example.com-blocked"
+            ]
+        );
+        assert_eq!(report.unknown_addresses[0].action, None);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].id, "unknown@example.com-blocked");
+        assert_eq!(report.failed[0].stage, Stage::Deactivate);
+        assert_eq!(
+            report.failed[0].description.as_deref(),
+            Some("created to block the address, but still active")
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_disables_catch_all_once_per_domain() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/api/v3/breacheddomain/example.com");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"first": ["Adobe"], "second": ["LinkedIn"]}"#);
+        });
+        let mut alias_service = FakeAliasService::new(vec![]);
+        alias_service.domains = vec![AliasDomain {
+            id: "example-com".to_string(),
+            domain: "example.com".to_string(),
+            kind: DomainKind::Custom,
+            active: true,
+            catch_all: true,
+        }];
+        let mut settings = test_settings();
+        settings.domain_search = true;
+        settings.policy.unknown_addresses = UnknownAddressAction::DisableCatchAll;
+        let test = TestScan::new(&server, alias_service, settings);
+
+        let report = test.scan().await;
+
+        assert_eq!(
+            *test.alias_service.catch_all_disabled.lock().unwrap(),
+            vec!["example-com"]
+        );
+        assert_eq!(report.unknown_addresses.len(), 2);
+        assert!(report
+            .unknown_addresses
+            .iter()
+            .all(|address| address.action == Some(UnknownAddressAction::DisableCatchAll)));
+        assert!(test.alias_service.blocked.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scan_marks_report_interrupted_on_shutdown() {
+        let server = MockServer::start();
+        mock_breaches(&server, "email@email.com", 404, "");
+        let alias_service = FakeAliasService::new(vec![FakeAlias::new("first", "email@email.com")]);
+        let test = TestScan::new(&server, alias_service, test_settings());
+        let (sender, shutdown) = Shutdown::channel();
+        sender.send(true).unwrap();
+
+        let report = test.scanner().scan(&shutdown).await.unwrap();
+
+        assert_eq!(report.aliases_checked, 0);
+        assert!(report.interrupted);
+        assert!(!report.is_complete());
+    }
+
+    #[tokio::test]
+    async fn scan_stops_when_unauthorized() {
+        let server = MockServer::start();
+        mock_breaches(&server, "email@email.com", 401, "Access denied");
+        let alias_service = FakeAliasService::new(vec![
+            FakeAlias::new("first", "email@email.com"),
+            FakeAlias::new("second", "email@email.com"),
+        ]);
+        let test = TestScan::new(&server, alias_service, test_settings());
+        let (_sender, shutdown) = Shutdown::channel();
+
+        let result = test.scanner().scan(&shutdown).await;
+
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ApiError>(),
+            Some(ApiError::Unauthorized { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn undo_reactivates_aliases_from_most_recent_run() {
+        let mut alias_service = FakeAliasService::new(vec![]);
+        alias_service.failing = vec!["failing".to_string()];
+        let earlier_run = Utc::now() - chrono::Duration::days(1);
+        let last_run = Utc::now();
+        let test = TestScan::new(&MockServer::start(), alias_service, test_settings());
+        test.state
+            .record_deactivation("earlier", earlier_run)
+            .unwrap();
+        test.state.record_deactivation("last", last_run).unwrap();
+        test.state.record_deactivation("failing", last_run).unwrap();
+
+        let report = test.scanner().undo(None).await.unwrap().unwrap();
+
+        assert_eq!(report.run, last_run);
+        assert_eq!(report.reactivated.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].stage, Stage::Reactivate);
+        assert_eq!(
+            *test.alias_service.reactivated.lock().unwrap(),
+            vec!["last"]
+        );
+        assert_eq!(
+            test.state.snapshot().undoable_runs(),
+            vec![earlier_run, last_run]
+        );
+    }
+
+    #[tokio::test]
+    async fn undo_continues_when_state_cannot_be_saved() {
+        let alias_service = FakeAliasService::new(vec![]);
+        let run = Utc::now();
+        let test = TestScan::new(&MockServer::start(), alias_service, test_settings());
+        test.state.record_deactivation("first", run).unwrap();
+        test.state.record_deactivation("second", run).unwrap();
+        std::fs::remove_dir_all(test.directory.path()).unwrap();
+
+        let report = test.scanner().undo(None).await.unwrap().unwrap();
+
+        assert_eq!(report.reactivated.len(), 2);
+        assert_eq!(report.failed.len(), 2);
+        assert_eq!(report.failed[0].stage, Stage::Reactivate);
+        assert!(report.failed[0]
+            .error
+            .starts_with("Could not save the state: "));
+        assert_eq!(
+            *test.alias_service.reactivated.lock().unwrap(),
+            vec!["first", "second"]
+        );
+    }
+
+    #[tokio::test]
+    async fn undo_returns_none_without_deactivations() {
+        let alias_service = FakeAliasService::new(vec![]);
+        let test = TestScan::new(&MockServer::start(), alias_service, test_settings());
+
+        assert!(test.scanner().undo(None).await.unwrap().is_none());
+    }
+}
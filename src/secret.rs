@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::path::PathBuf;
+
+use secret_service::{EncryptionType, SecretService};
+use serde::Deserialize;
+
+/// A token kept out of `Debug` output, so it cannot end up in logs by accident.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    /// The actual value, for when it needs to be sent.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+/// Where a secret is read from.
+///
+/// In the configuration file, this is either the secret itself or a table naming where to find it, e.g.
+/// `token = { file = "/run/secrets/hibp_token" }`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "a string, or a table with one of `file`, `command` or `keyring`"
+)]
+pub enum SecretSource {
+    Value(Secret),
+    /// A file containing the secret, such as a Docker or Kubernetes secret.
+    File {
+        file: PathBuf,
+    },
+    /// A shell command that prints the secret on the first line of its output, such as `pass show hibp`.
+    Command {
+        command: String,
+    },
+    /// An item in the OS keyring, looked up through the Secret Service API.
+    Keyring {
+        keyring: KeyringEntry,
+    },
+}
+
+/// The attributes identifying an item in the keyring, as used by `secret-tool` and most keyring libraries.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct KeyringEntry {
+    pub service: String,
+    pub username: String,
+}
+
+impl SecretSource {
+    /// Reads the secret, with any trailing newline removed.
+    pub async fn read(&self) -> Result<Secret, SecretError> {
+        match self {
+            SecretSource::Value(secret) => Ok(secret.clone()),
+            SecretSource::File { file } => {
+                let contents = tokio::fs::read_to_string(file).await.map_err(|error| {
+                    SecretError::new(format!("unable to read {}: {}", file.display(), error))
+                })?;
+                Ok(Secret::new(contents.trim_end_matches(['\r', '\n'])))
+            }
+            SecretSource::Command { command } => {
+                let output = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .output()
+                    .await
+                    .map_err(|error| {
+                        SecretError::new(format!("unable to run `{}`: {}", command, error))
+                    })?;
+                if !output.status.success() {
+                    return Err(SecretError::new(format!(
+                        "`{}` failed with {}: {}",
+                        command,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                let stdout = String::from_utf8(output.stdout).map_err(|_| {
+                    SecretError::new(format!("`{}` did not print valid UTF-8", command))
+                })?;
+                Ok(Secret::new(stdout.lines().next().unwrap_or("")))
+            }
+            SecretSource::Keyring { keyring } => keyring.read().await,
+        }
+    }
+}
+
+impl KeyringEntry {
+    async fn read(&self) -> Result<Secret, SecretError> {
+        let keyring_error =
+            |error: secret_service::Error| SecretError::new(format!("keyring error: {}", error));
+        let service = SecretService::connect(EncryptionType::Dh)
+            .await
+            .map_err(keyring_error)?;
+        let items = service
+            .search_items(HashMap::from([
+                ("service", self.service.as_str()),
+                ("username", self.username.as_str()),
+            ]))
+            .await
+            .map_err(keyring_error)?;
+        let item = match (items.unlocked.first(), items.locked.first()) {
+            (Some(item), _) => item,
+            (None, Some(item)) => {
+                item.unlock().await.map_err(keyring_error)?;
+                item
+            }
+            (None, None) => {
+                return Err(SecretError::new(format!(
+                    "no keyring item found for service \"{}\" and username \"{}\"",
+                    self.service, self.username
+                )))
+            }
+        };
+        let secret = item.get_secret().await.map_err(keyring_error)?;
+        String::from_utf8(secret)
+            .map(Secret::new)
+            .map_err(|_| SecretError::new("the keyring item is not valid UTF-8".to_string()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecretError {
+    pub message: String,
+}
+
+impl SecretError {
+    pub fn new(message: String) -> Self {
+        SecretError { message }
+    }
+}
+
+impl Display for SecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SecretError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Wrapper {
+        token: SecretSource,
+    }
+
+    fn source(toml: &str) -> Result<SecretSource, toml::de::Error> {
+        toml::from_str::<Wrapper>(toml).map(|wrapper| wrapper.token)
+    }
+
+    #[test]
+    fn debug_does_not_show_secret() {
+        let secret = Secret::new("super-secret");
+
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+        assert_eq!(
+            format!("{:?}", SecretSource::Value(secret)),
+            "Value(Secret(***))"
+        );
+    }
+
+    #[test]
+    fn deserialises_each_source() {
+        assert_eq!(
+            source(r#"token = "value""#).unwrap(),
+            SecretSource::Value(Secret::new("value"))
+        );
+        assert_eq!(
+            source(r#"token = { file = "/run/secrets/token" }"#).unwrap(),
+            SecretSource::File {
+                file: PathBuf::from("/run/secrets/token")
+            }
+        );
+        assert_eq!(
+            source(r#"token = { command = "pass show token" }"#).unwrap(),
+            SecretSource::Command {
+                command: "pass show token".to_string()
+            }
+        );
+        assert_eq!(
+            source(r#"token = { keyring = { service = "hibp", username = "me" } }"#).unwrap(),
+            SecretSource::Keyring {
+                keyring: KeyringEntry {
+                    service: "hibp".to_string(),
+                    username: "me".to_string()
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn deserialise_returns_error_for_unknown_source() {
+        assert!(source(r#"token = { vault = "secret/token" }"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn read_file_trims_trailing_newline() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("token");
+        std::fs::write(&path, "file-token\n").unwrap();
+
+        let secret = SecretSource::File { file: path }.read().await.unwrap();
+
+        assert_eq!(secret.expose(), "file-token");
+    }
+
+    #[tokio::test]
+    async fn read_file_returns_error_for_missing_file() {
+        let directory = tempdir().unwrap();
+
+        let error = SecretSource::File {
+            file: directory.path().join("missing"),
+        }
+        .read()
+        .await
+        .unwrap_err();
+
+        assert!(error.message.starts_with("unable to read"));
+    }
+
+    #[tokio::test]
+    async fn read_command_returns_first_line() {
+        let secret = SecretSource::Command {
+            command: "printf 'command-token\\nurl: example.com\\n'".to_string(),
+        }
+        .read()
+        .await
+        .unwrap();
+
+        assert_eq!(secret.expose(), "command-token");
+    }
+
+    #[tokio::test]
+    async fn read_command_returns_error_for_failure() {
+        let error = SecretSource::Command {
+            command: "echo 'not in the password store' >&2; exit 1".to_string(),
+        }
+        .read()
+        .await
+        .unwrap_err();
+
+        assert!(error.message.contains("not in the password store"));
+    }
+}
@@ -0,0 +1,60 @@
+use axum::routing::get;
+use axum::Router;
+use log::info;
+use tokio::net::TcpListener;
+
+use crate::dashboard::{self, Dashboard};
+use crate::metrics;
+use crate::shutdown::Shutdown;
+
+fn router(dashboard: Dashboard) -> Router {
+    Router::new()
+        .route("/metrics", get(|| async { metrics::render() }))
+        .merge(dashboard::router(dashboard))
+}
+
+/// Serves the HTTP endpoints available in daemon mode until shutdown is requested.
+pub async fn serve(
+    listener: TcpListener,
+    dashboard: Dashboard,
+    mut shutdown: Shutdown,
+) -> std::io::Result<()> {
+    info!("Listening on http://{}.", listener.local_addr()?);
+    axum::serve(listener, router(dashboard))
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::dashboard::tests::test_dashboard;
+
+    #[tokio::test]
+    #[serial]
+    async fn serve_exposes_metrics_and_dashboard() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, shutdown) = Shutdown::channel();
+        let (mut dashboard, _, _directory) = test_dashboard(vec![]);
+        dashboard.hosts = dashboard::allowed_hosts(address, &[]);
+        let server = tokio::spawn(serve(listener, dashboard, shutdown));
+
+        let response = reqwest::get(format!("http://{}/metrics", address))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("has_my_alias_been_pwned_aliases_scanned_total"));
+
+        let response = reqwest::get(format!("http://{}/", address)).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+
+        sender.send(true).unwrap();
+        assert!(server.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,79 @@
+use log::info;
+use tokio::sync::watch;
+
+/// Tracks whether the application has been asked to stop, so that long running work can finish what it is doing and
+/// exit cleanly rather than being killed part way through.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Creates a new instance along with the sender used to trigger it.
+    pub fn channel() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, Shutdown { receiver })
+    }
+
+    /// Creates a new instance that is triggered when the process receives `SIGTERM` or `Ctrl+C`.
+    pub fn listen() -> Self {
+        let (sender, shutdown) = Shutdown::channel();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            info!("Shutdown requested, finishing current work.");
+            let _ = sender.send(true);
+        });
+        shutdown
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until shutdown has been requested.
+    pub async fn wait(&mut self) {
+        let _ = self.receiver.wait_for(|requested| *requested).await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn is_requested_returns_false_until_triggered() {
+        let (sender, shutdown) = Shutdown::channel();
+
+        assert!(!shutdown.is_requested());
+
+        sender.send(true).unwrap();
+
+        assert!(shutdown.is_requested());
+    }
+
+    #[tokio::test]
+    async fn wait_returns_once_triggered() {
+        let (sender, mut shutdown) = Shutdown::channel();
+
+        sender.send(true).unwrap();
+        shutdown.wait().await;
+
+        assert!(shutdown.is_requested());
+    }
+}
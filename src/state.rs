@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::email_alias::Alias;
+
+/// Everything remembered between runs, keyed by alias ID.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct State {
+    #[serde(default)]
+    pub aliases: BTreeMap<String, AliasState>,
+    /// Every action taken on an alias, oldest first, so that a run can be undone.
+    #[serde(default)]
+    pub actions: Vec<ActionRecord>,
+}
+
+impl State {
+    /// When each run that deactivated or replaced aliases still to be undone started, oldest first.
+    pub fn undoable_runs(&self) -> Vec<DateTime<Utc>> {
+        let mut runs: Vec<DateTime<Utc>> = self
+            .actions
+            .iter()
+            .filter(|action| action.is_undoable())
+            .map(|action| action.run)
+            .collect();
+        runs.sort();
+        runs.dedup();
+        runs
+    }
+
+    /// Finds the ID of an alias from either its ID or its email address.
+    pub fn find_alias(&self, alias: &str) -> Option<&str> {
+        self.aliases
+            .iter()
+            .find(|(id, alias_state)| *id == alias || alias_state.email == alias)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// The deactivations and replacements from the run that have not been undone yet.
+    pub fn undoable_actions(&self, run: DateTime<Utc>) -> Vec<&ActionRecord> {
+        self.actions
+            .iter()
+            .filter(|action| action.run == run && action.is_undoable())
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Deactivated,
+    Reactivated,
+    Deleted,
+    Tagged,
+    /// Deactivated after a new alias was created to take its place.
+    Replaced,
+}
+
+impl ActionKind {
+    /// Whether the alias is left deactivated, and so can be reactivated to undo the action.
+    fn deactivates(self) -> bool {
+        matches!(self, ActionKind::Deactivated | ActionKind::Replaced)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActionRecord {
+    /// When the scan or dashboard request that took the action started, shared by every action it took.
+    pub run: DateTime<Utc>,
+    pub at: DateTime<Utc>,
+    pub alias_id: String,
+    pub email: String,
+    pub kind: ActionKind,
+    /// Whether the action has since been reversed.
+    #[serde(default)]
+    pub undone: bool,
+}
+
+impl ActionRecord {
+    fn is_undoable(&self) -> bool {
+        self.kind.deactivates() && !self.undone
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AliasState {
+    pub email: String,
+    pub description: Option<String>,
+    pub active: bool,
+    pub last_checked: Option<DateTime<Utc>>,
+    /// Names of the breaches the alias was found in when last checked.
+    #[serde(default)]
+    pub breaches: Vec<String>,
+    /// Names of the breaches that have been acknowledged as dealt with.
+    #[serde(default)]
+    pub acknowledged: Vec<String>,
+}
+
+impl AliasState {
+    /// Breaches that have been found, but not yet acknowledged.
+    pub fn unacknowledged_breaches(&self) -> Vec<&str> {
+        self.breaches
+            .iter()
+            .filter(|breach| !self.acknowledged.contains(breach))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Persists [`State`] as JSON to a file, writing it back after every change.
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl StateStore {
+    /// Loads the state from the given file, starting empty if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let state = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            State::default()
+        };
+        Ok(StateStore {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Returns a copy of the current state.
+    pub fn snapshot(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Applies the change to the state and saves it.
+    ///
+    /// The state is written to a temporary file that then replaces the old one, so the file is never left half
+    /// written, and the change is only kept in memory once it has been saved.
+    pub fn update<F>(&self, change: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnOnce(&mut State),
+    {
+        let mut state = self.state.lock().unwrap();
+        let mut changed = state.clone();
+        change(&mut changed);
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(&changed)?)?;
+        std::fs::rename(&temporary, &self.path)?;
+        *state = changed;
+        Ok(())
+    }
+
+    /// Records the breaches found when checking the alias.
+    pub fn record_check(
+        &self,
+        alias: &dyn Alias,
+        breaches: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|state| {
+            let alias_state = update_details(state, alias);
+            alias_state.last_checked = Some(Utc::now());
+            alias_state.breaches = breaches;
+        })
+    }
+
+    /// Records that the alias has been deactivated as part of the run.
+    pub fn record_deactivation(
+        &self,
+        id: &str,
+        run: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record_action(id, run, ActionKind::Deactivated)
+    }
+
+    /// Records an action taken on the alias as part of the run.
+    pub fn record_action(
+        &self,
+        id: &str,
+        run: DateTime<Utc>,
+        kind: ActionKind,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|state| record_action(state, id, run, kind))
+    }
+
+    /// Records that the alias has been reactivated, undoing any earlier deactivations or replacements of it.
+    pub fn record_reactivation(
+        &self,
+        id: &str,
+        run: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|state| {
+            for action in &mut state.actions {
+                if action.alias_id == id && action.kind.deactivates() {
+                    action.undone = true;
+                }
+            }
+            record_action(state, id, run, ActionKind::Reactivated);
+        })
+    }
+
+    /// Marks all the breaches currently found for the alias as acknowledged.
+    pub fn acknowledge_all(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.acknowledge(id, &[])
+    }
+
+    /// Marks the breaches of the alias as acknowledged, or all those currently found if none are given. Breaches not
+    /// found yet can be acknowledged too, so that they are ignored once found.
+    pub fn acknowledge(
+        &self,
+        id: &str,
+        breaches: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|state| {
+            if let Some(alias_state) = state.aliases.get_mut(id) {
+                let breaches = if breaches.is_empty() {
+                    alias_state.breaches.clone()
+                } else {
+                    breaches.to_vec()
+                };
+                for breach in breaches {
+                    if !alias_state.acknowledged.contains(&breach) {
+                        alias_state.acknowledged.push(breach);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Removes the acknowledgement of the breaches of the alias, or of all of them if none are given.
+    pub fn unacknowledge(
+        &self,
+        id: &str,
+        breaches: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|state| {
+            if let Some(alias_state) = state.aliases.get_mut(id) {
+                alias_state
+                    .acknowledged
+                    .retain(|breach| !breaches.is_empty() && !breaches.contains(breach));
+            }
+        })
+    }
+
+    /// The names of the breaches acknowledged for the alias.
+    pub fn acknowledged(&self, id: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .aliases
+            .get(id)
+            .map(|alias_state| alias_state.acknowledged.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn record_action(state: &mut State, id: &str, run: DateTime<Utc>, kind: ActionKind) {
+    let email = match state.aliases.get_mut(id) {
+        Some(alias_state) => {
+            match kind {
+                ActionKind::Reactivated => alias_state.active = true,
+                ActionKind::Tagged => {}
+                ActionKind::Deactivated | ActionKind::Deleted | ActionKind::Replaced => {
+                    alias_state.active = false
+                }
+            }
+            alias_state.email.clone()
+        }
+        None => String::new(),
+    };
+    state.actions.push(ActionRecord {
+        run,
+        at: Utc::now(),
+        alias_id: id.to_string(),
+        email,
+        kind,
+        undone: false,
+    });
+}
+
+fn update_details<'a>(state: &'a mut State, alias: &dyn Alias) -> &'a mut AliasState {
+    let alias_state = state.aliases.entry(alias.get_id().to_string()).or_default();
+    alias_state.email = alias.get_email().to_string();
+    alias_state.description = alias.get_description().map(str::to_string);
+    alias_state.active = alias.is_active();
+    alias_state
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::email_alias::fakes::FakeAlias;
+
+    fn test_alias() -> FakeAlias {
+        FakeAlias {
+            description: Some("description".to_string()),
+            ..FakeAlias::new("test-id", "email@email.com")
+        }
+    }
+
+    #[test]
+    fn load_returns_empty_state_if_file_does_not_exist() {
+        let directory = tempdir().unwrap();
+
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+
+        assert_eq!(store.snapshot(), State::default());
+    }
+
+    #[test]
+    fn load_returns_error_for_invalid_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("state.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let store = StateStore::load(&path);
+
+        assert!(store.is_err());
+    }
+
+    #[test]
+    fn update_keeps_state_unchanged_if_it_cannot_be_saved() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("missing").join("state.json")).unwrap();
+
+        let result = store.record_check(&test_alias(), vec!["Adobe".to_string()]);
+
+        assert!(result.is_err());
+        assert_eq!(store.snapshot(), State::default());
+    }
+
+    #[test]
+    fn update_replaces_file_without_leaving_temporary_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("state.json");
+        let store = StateStore::load(&path).unwrap();
+
+        store.record_check(&test_alias(), vec![]).unwrap();
+        store
+            .record_check(&test_alias(), vec!["Adobe".to_string()])
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["state.json"]);
+        let reloaded = StateStore::load(&path).unwrap().snapshot();
+        assert_eq!(reloaded.aliases["test-id"].breaches, vec!["Adobe"]);
+    }
+
+    #[test]
+    fn record_check_is_persisted() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("state.json");
+        let store = StateStore::load(&path).unwrap();
+
+        store
+            .record_check(&test_alias(), vec!["Adobe".to_string()])
+            .unwrap();
+
+        let reloaded = StateStore::load(&path).unwrap().snapshot();
+        let alias_state = reloaded.aliases.get("test-id").unwrap();
+        assert_eq!(alias_state.email, "email@email.com");
+        assert_eq!(alias_state.description.as_deref(), Some("description"));
+        assert!(alias_state.active);
+        assert!(alias_state.last_checked.is_some());
+        assert_eq!(alias_state.breaches, vec!["Adobe".to_string()]);
+    }
+
+    #[test]
+    fn record_deactivation_marks_alias_inactive() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store.record_check(&test_alias(), vec![]).unwrap();
+
+        store.record_deactivation("test-id", Utc::now()).unwrap();
+
+        let state = store.snapshot();
+        assert!(!state.aliases.get("test-id").unwrap().active);
+        assert_eq!(state.actions.len(), 1);
+        assert_eq!(state.actions[0].kind, ActionKind::Deactivated);
+        assert_eq!(state.actions[0].email, "email@email.com");
+    }
+
+    #[test]
+    fn undoable_runs_returns_runs_with_deactivations_not_undone() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        let first_run = Utc::now() - chrono::Duration::days(1);
+        let second_run = Utc::now();
+        store.record_deactivation("first", first_run).unwrap();
+        store.record_deactivation("second", second_run).unwrap();
+        store.record_deactivation("third", second_run).unwrap();
+
+        let state = store.snapshot();
+
+        assert_eq!(state.undoable_runs(), vec![first_run, second_run]);
+        let actions = state.undoable_actions(second_run);
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].alias_id, "second");
+        assert_eq!(actions[1].alias_id, "third");
+    }
+
+    #[test]
+    fn record_reactivation_undoes_deactivation() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store.record_check(&test_alias(), vec![]).unwrap();
+        let run = Utc::now();
+        store.record_deactivation("test-id", run).unwrap();
+
+        store.record_reactivation("test-id", Utc::now()).unwrap();
+
+        let state = store.snapshot();
+        assert!(state.aliases.get("test-id").unwrap().active);
+        assert!(state.actions[0].undone);
+        assert_eq!(state.actions[1].kind, ActionKind::Reactivated);
+        assert!(state.undoable_runs().is_empty());
+    }
+
+    #[test]
+    fn replacements_are_undoable_but_tags_and_deletions_are_not() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store.record_check(&test_alias(), vec![]).unwrap();
+        let run = Utc::now();
+        store
+            .record_action("test-id", run, ActionKind::Tagged)
+            .unwrap();
+        assert!(store.snapshot().aliases.get("test-id").unwrap().active);
+        store
+            .record_action("deleted", run, ActionKind::Deleted)
+            .unwrap();
+        store
+            .record_action("test-id", run, ActionKind::Replaced)
+            .unwrap();
+
+        let state = store.snapshot();
+
+        assert!(!state.aliases.get("test-id").unwrap().active);
+        let actions = state.undoable_actions(run);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].kind, ActionKind::Replaced);
+    }
+
+    #[test]
+    fn acknowledge_all_acknowledges_current_breaches() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store
+            .record_check(&test_alias(), vec!["Adobe".to_string()])
+            .unwrap();
+
+        store.acknowledge_all("test-id").unwrap();
+
+        let state = store.snapshot();
+        let alias_state = state.aliases.get("test-id").unwrap();
+        assert_eq!(alias_state.acknowledged, vec!["Adobe".to_string()]);
+        assert!(alias_state.unacknowledged_breaches().is_empty());
+    }
+
+    #[test]
+    fn acknowledge_and_unacknowledge_named_breaches() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store
+            .record_check(&test_alias(), vec!["Adobe".to_string()])
+            .unwrap();
+
+        store
+            .acknowledge("test-id", &["LinkedIn".to_string(), "Adobe".to_string()])
+            .unwrap();
+        assert_eq!(store.acknowledged("test-id"), vec!["LinkedIn", "Adobe"]);
+
+        store
+            .unacknowledge("test-id", &["Adobe".to_string()])
+            .unwrap();
+        assert_eq!(store.acknowledged("test-id"), vec!["LinkedIn"]);
+
+        store.unacknowledge("test-id", &[]).unwrap();
+        assert!(store.acknowledged("test-id").is_empty());
+    }
+
+    #[test]
+    fn find_alias_matches_id_or_email() {
+        let directory = tempdir().unwrap();
+        let store = StateStore::load(&directory.path().join("state.json")).unwrap();
+        store.record_check(&test_alias(), vec![]).unwrap();
+        let state = store.snapshot();
+
+        assert_eq!(state.find_alias("test-id"), Some("test-id"));
+        assert_eq!(state.find_alias("email@email.com"), Some("test-id"));
+        assert_eq!(state.find_alias("unknown@email.com"), None);
+    }
+
+    #[test]
+    fn unacknowledged_breaches_excludes_acknowledged() {
+        let alias_state = AliasState {
+            breaches: vec!["Adobe".to_string(), "BattlefieldHeroes".to_string()],
+            acknowledged: vec!["Adobe".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            alias_state.unacknowledged_breaches(),
+            vec!["BattlefieldHeroes"]
+        );
+    }
+}